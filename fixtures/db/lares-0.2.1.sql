@@ -0,0 +1,41 @@
+CREATE TABLE IF NOT EXISTS `group` (
+    id INTEGER PRIMARY KEY,
+    title TEXT UNIQUE ON CONFLICT IGNORE
+);
+CREATE TABLE IF NOT EXISTS `feed` (
+    id INTEGER PRIMARY KEY,
+    title TEXT,
+    url TEXT,
+    site_url TEXT,
+    is_spark BOOLEAN,
+    last_updated DATETIME
+);
+CREATE TABLE IF NOT EXISTS `feed_group` (
+    id INTEGER PRIMARY KEY,
+    group_id INTEGER,
+    feed_id INTEGER,
+    UNIQUE(group_id, feed_id) ON CONFLICT IGNORE
+);
+CREATE TABLE IF NOT EXISTS `favicon` (
+    id INTEGER PRIMARY KEY,
+    data BLOB
+);
+CREATE TABLE IF NOT EXISTS `item` (
+    id INTEGER PRIMARY KEY,
+    feed_id INTEGER,
+    title TEXT,
+    author TEXT,
+    html BLOB,
+    url TEXT,
+    is_saved BOOLEAN,
+    is_read BOOLEAN,
+    created DATETIME
+);
+INSERT INTO `group` (id, title) VALUES (1, 'Rust');
+INSERT INTO `feed` (id, title, url, site_url, is_spark, last_updated)
+VALUES (1, 'Rust Blog', 'https://blog.rust-lang.org/feed.xml', 'https://blog.rust-lang.org/', 0,
+    '2020-08-04T00:00:00+00:00');
+INSERT INTO `feed_group` (id, group_id, feed_id) VALUES (1, 1, 1);
+INSERT INTO `item` (id, feed_id, title, author, html, url, is_saved, is_read, created)
+VALUES (1, 1, 'Announcing Rust 1.45.2', 'The Rust Release Team', '<p>Rust 1.45.2</p>',
+    'https://blog.rust-lang.org/2020/08/03/Rust-1.45.2.html', 1, 1, '2020-08-03T00:00:00+00:00');
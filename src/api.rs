@@ -1,12 +1,14 @@
+use chrono::{Duration, Utc};
 use serde::Deserialize;
 use serde_json::json;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::future::Future;
 use std::pin::Pin;
 use tide::{log, Request};
 
-use crate::model::{Feed, FeedGroup, Group, Item, ModelExt};
+use crate::model::{Favicon, Feed, FeedGroup, Group, Item, ItemFilter, Link, ModelExt};
 use crate::state::State;
 use crate::utils::comma_join_vec;
 
@@ -16,7 +18,7 @@ fn handle_groups(request: Request<State>) -> Result<impl Into<tide::Response>, t
     log::info!("requesting groups");
     let (groups, feed_groups) = {
         let conn = request.state().db.get()?;
-        (Group::all(&conn)?, FeedGroup::all(&conn)?)
+        (Group::all_with_paths(&conn)?, FeedGroup::rolled_up(&conn)?)
     };
 
     Ok(json!({
@@ -31,7 +33,7 @@ fn handle_feeds(request: Request<State>) -> Result<impl Into<tide::Response>, ti
     log::info!("requesting feeds");
     let (feeds, feed_groups) = {
         let conn = request.state().db.get()?;
-        (Feed::all(&conn)?, FeedGroup::all(&conn)?)
+        (Feed::all(&conn)?, FeedGroup::rolled_up(&conn)?)
     };
 
     Ok(json!({
@@ -42,14 +44,32 @@ fn handle_feeds(request: Request<State>) -> Result<impl Into<tide::Response>, ti
     }))
 }
 
+fn handle_favicons(request: Request<State>) -> Result<impl Into<tide::Response>, tide::Error> {
+    log::info!("requesting favicons");
+    let favicons = {
+        let conn = request.state().db.get()?;
+        Favicon::all(&conn)?
+    };
+
+    Ok(json!({
+        "api_version": API_VERSION,
+        "auth": 1,
+        "favicons": favicons,
+    }))
+}
+
 fn handle_items(
     request: Request<State>,
-    since_id: Option<u32>,
+    query: ItemsQuery,
 ) -> Result<impl Into<tide::Response>, tide::Error> {
-    log::info!("requesting items (since = {:?})", since_id);
+    log::info!("requesting items ({:?})", query);
     let (count, items) = {
         let conn = request.state().db.get()?;
-        (Item::count(&conn)?, Item::select(&conn, since_id, false)?)
+        let items = match query.with_ids {
+            Some(ids) => Item::with_ids(&conn, &ids)?,
+            None => Item::select(&conn, query.since_id, query.max_id)?,
+        };
+        (Item::count(&conn)?, items)
     };
     Ok(json!({
         "api_version": API_VERSION,
@@ -59,6 +79,40 @@ fn handle_items(
     }))
 }
 
+fn handle_search(
+    request: Request<State>,
+    query: SearchQuery,
+) -> Result<impl Into<tide::Response>, tide::Error> {
+    log::info!("searching items ({:?})", query);
+    let items = {
+        let conn = request.state().db.get()?;
+        Item::search(&conn, &query.text, &query.filter, 50)?
+    };
+    Ok(json!({
+        "api_version": API_VERSION,
+        "auth": 1,
+        "items": items,
+    }))
+}
+
+fn handle_links(
+    request: Request<State>,
+    query: LinksQuery,
+) -> Result<impl Into<tide::Response>, tide::Error> {
+    log::info!("requesting links ({:?})", query);
+    let end = Utc::now() - Duration::days(query.offset as i64);
+    let start = end - Duration::days(query.range as i64);
+    let links = {
+        let conn = request.state().db.get()?;
+        Link::hot(&conn, start, end, query.page)?
+    };
+    Ok(json!({
+        "api_version": API_VERSION,
+        "auth": 1,
+        "links": links,
+    }))
+}
+
 fn handle_unread_item_ids(
     request: Request<State>,
 ) -> Result<impl Into<tide::Response>, tide::Error> {
@@ -106,6 +160,9 @@ fn handle_write_form(
                 Action::Read => {
                     item.read(&conn)?;
                 }
+                Action::Unread => {
+                    item.mark_unread(&conn)?;
+                }
                 Action::Saved => {
                     item.save(&conn)?;
                 }
@@ -122,36 +179,38 @@ fn handle_write_form(
             };
 
             match form.r#as {
-                Action::Saved | Action::Unsaved => bail!(400, "invalid as"),
+                Action::Saved | Action::Unsaved | Action::Unread => bail!(400, "invalid as"),
                 Action::Read => {
-                    feed.read(form.before);
+                    let conn = request.state().db.get()?;
+                    feed.read(&conn, form.before)?;
                     handle_ok(request)
                 }
             }
         }
-        MarkType::Group => {
-            if form.id == 0 {
-                // TODO: Kindling
-                bail!(400, "unimplemented");
-            } else if form.id == -1 {
-                // TODO: Sparks
-                bail!(400, "unimplemented");
-            };
-
-            let group = {
+        MarkType::Group => match form.r#as {
+            Action::Saved | Action::Unsaved | Action::Unread => bail!(400, "invalid as"),
+            Action::Read => {
                 let conn = request.state().db.get()?;
-                Group::get(&conn, form.id as u32)?
-            };
-
-            match form.r#as {
-                Action::Saved | Action::Unsaved => bail!(400, "invalid as"),
-                Action::Read => {
-                    group.read(form.before);
-                    handle_ok(request)
-                }
+                match form.id {
+                    0 => Group::read_kindling(&conn, form.before)?,
+                    -1 => Group::read_sparks(&conn, form.before)?,
+                    id => Group::get(&conn, id as u32)?.read(&conn, form.before)?,
+                };
+                handle_ok(request)
             }
-        }
+        },
+    }
+}
+
+fn handle_unread_recently_read(
+    request: Request<State>,
+) -> Result<impl Into<tide::Response>, tide::Error> {
+    log::info!("marking recently read items as unread");
+    {
+        let conn = request.state().db.get()?;
+        Item::unread_recently_read(&conn)?;
     }
+    handle_ok(request)
 }
 
 fn handle_ok(_request: Request<State>) -> Result<impl Into<tide::Response>, tide::Error> {
@@ -173,6 +232,7 @@ enum MarkType {
 #[serde(rename_all = "lowercase")]
 enum Action {
     Read,
+    Unread,
     Saved,
     Unsaved,
 }
@@ -185,6 +245,85 @@ struct WriteForm {
     before: Option<u32>,
 }
 
+#[derive(Debug)]
+struct ItemsQuery {
+    since_id: Option<u32>,
+    max_id: Option<u32>,
+    with_ids: Option<Vec<u32>>,
+}
+
+impl ItemsQuery {
+    fn from_query(query: &HashMap<Cow<'_, str>, Cow<'_, str>>) -> Self {
+        ItemsQuery {
+            since_id: query.get("since_id").and_then(|x| x.parse().ok()),
+            max_id: query.get("max_id").and_then(|x| x.parse().ok()),
+            with_ids: query.get("with_ids").map(|x| {
+                x.split(',')
+                    .filter_map(|id| id.trim().parse().ok())
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// Full-text search, not part of the Fever API.
+#[derive(Debug)]
+struct SearchQuery {
+    text: String,
+    filter: ItemFilter,
+}
+
+impl SearchQuery {
+    fn from_query(query: &HashMap<Cow<'_, str>, Cow<'_, str>>) -> Self {
+        SearchQuery {
+            text: query
+                .get("q")
+                .map(|x| x.clone().into_owned())
+                .unwrap_or_default(),
+            filter: ItemFilter {
+                feed_id: query.get("feed_id").and_then(|x| x.parse().ok()),
+                group_id: query.get("group_id").and_then(|x| x.parse().ok()),
+                unread: query.get("unread").map(|x| x == "1").unwrap_or(false),
+                saved: query.get("saved").map(|x| x == "1").unwrap_or(false),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Hot links window: `range` days ending `offset` days ago.
+#[derive(Debug)]
+struct LinksQuery {
+    offset: u32,
+    range: u32,
+    page: u32,
+}
+
+impl LinksQuery {
+    /// Longest `offset` and `range` accepted (unit: days). Larger values would reach dates out
+    /// of range, and no link is that old anyway.
+    const MAX_DAYS: u32 = 100 * 365;
+
+    fn from_query(query: &HashMap<Cow<'_, str>, Cow<'_, str>>) -> Self {
+        let get = |key: &str, default: u32| {
+            query
+                .get(key)
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(default)
+        };
+        LinksQuery {
+            offset: get("offset", 0).min(Self::MAX_DAYS),
+            range: get("range", 7).min(Self::MAX_DAYS),
+            page: get("page", 1),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct UnreadRecentlyRead {
+    unread_recently_read: u32,
+}
+
 #[derive(Deserialize, Debug)]
 struct Auth {
     api_key: String,
@@ -238,20 +377,34 @@ pub fn make_app(state: State) -> tide::Server<State> {
             Ok("")
         })
         .post(|mut request: Request<State>| async move {
-            let form = request.body_form::<WriteForm>().await;
+            let body = request.body_string().await.unwrap_or_default();
+            let form = serde_urlencoded::from_str::<WriteForm>(&body);
+            let unread_recently_read = serde_urlencoded::from_str::<UnreadRecentlyRead>(&body)
+                .map(|x| x.unread_recently_read == 1)
+                .unwrap_or(false);
             let query = request.url().query_pairs().collect::<HashMap<_, _>>();
 
             let resp = if query.contains_key("groups") {
                 handle_groups(request)?.into()
             } else if query.contains_key("feeds") {
                 handle_feeds(request)?.into()
+            } else if query.contains_key("favicons") {
+                handle_favicons(request)?.into()
             } else if query.contains_key("items") {
-                let since_id = query.get("since_id").and_then(|x| x.parse().ok());
-                handle_items(request, since_id)?.into()
+                let items_query = ItemsQuery::from_query(&query);
+                handle_items(request, items_query)?.into()
+            } else if query.contains_key("search") {
+                let search_query = SearchQuery::from_query(&query);
+                handle_search(request, search_query)?.into()
+            } else if query.contains_key("links") {
+                let links_query = LinksQuery::from_query(&query);
+                handle_links(request, links_query)?.into()
             } else if query.contains_key("unread_item_ids") {
                 handle_unread_item_ids(request)?.into()
             } else if query.contains_key("saved_item_ids") {
                 handle_saved_item_ids(request)?.into()
+            } else if unread_recently_read || query.contains_key("unread_recently_read") {
+                handle_unread_recently_read(request)?.into()
             } else if let Ok(form) = form {
                 handle_write_form(request, form)?.into()
             } else {
@@ -1,5 +1,7 @@
 use anyhow::{anyhow, Context, Result};
 use async_std::prelude::FutureExt;
+use chrono::{DateTime, Utc};
+use difference::{Changeset, Difference};
 use either::Either;
 use futures::stream::{self, StreamExt};
 use log::{info, warn};
@@ -8,11 +10,31 @@ use std::io::{self, BufRead, Write};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
-use crate::model::{Feed, FeedGroup, Group, Item, ModelExt};
+use crate::crawler::Crawler;
+use crate::model::{self, Feed, FeedGroup, Group, Item, ItemFilter, ItemRevision, ModelExt};
 use crate::opml;
 use crate::remote::RemoteFeed;
 use crate::state::State;
 
+/// Renders a numeric default for structopt, which wants a string that outlives the parser.
+fn default_str(value: impl ToString) -> &'static str {
+    Box::leak(value.to_string().into_boxed_str())
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".to_owned())
+}
+
+/// Formats group paths the way the Fever API names nested groups, e.g. `Tech / Rust`.
+fn format_group_paths(paths: &[Vec<String>]) -> String {
+    paths
+        .iter()
+        .map(|path| path.join(" / "))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 #[derive(Debug, StructOpt)]
 pub enum FeedCommand {
     /// Lists all feeds
@@ -31,8 +53,33 @@ pub enum FeedCommand {
     /// Crawls a feed manually
     Crawl { id: u32 },
 
-    /// Imports OPML file
-    Import { file: PathBuf },
+    /// Shows crawl status of a feed
+    Status { id: u32 },
+
+    /// Sets crawl interval of a feed (unit: minutes), or resets it when omitted
+    SetInterval { id: u32, minutes: Option<u32> },
+
+    /// Changes the name or URLs of a feed
+    Edit {
+        id: u32,
+        #[structopt(long = "title")]
+        title: Option<String>,
+        #[structopt(long = "url")]
+        url: Option<String>,
+        #[structopt(long = "site-url")]
+        site_url: Option<String>,
+    },
+
+    /// Imports OPML file, skipping feeds which already exist
+    Import {
+        file: PathBuf,
+        #[structopt(long = "dry-run")]
+        /// Only shows which feeds and groups would be added
+        dry_run: bool,
+    },
+
+    /// Exports all feeds as OPML, to stdout unless a file is given
+    Export { file: Option<PathBuf> },
 }
 
 impl FeedCommand {
@@ -43,10 +90,16 @@ impl FeedCommand {
         };
         let mut table = Table::new();
         table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-        table.set_titles(row!["id", "name", "feed url"]);
+        table.set_titles(row!["id", "name", "feed url", "errors", "last success"]);
 
         for feed in feeds.into_iter() {
-            table.add_row(row![feed.id, feed.title, feed.url]);
+            table.add_row(row![
+                feed.id,
+                feed.title,
+                feed.url,
+                feed.error_count,
+                format_time(feed.last_success)
+            ]);
         }
 
         table.printstd();
@@ -164,10 +217,8 @@ impl FeedCommand {
 
     fn delete(state: State, id: u32) -> Result<()> {
         let conn = state.db.get()?;
-        let feed = Feed::get(&conn, id)?;
-        FeedGroup::delete_by_feed(&conn, feed.id)?;
-        Item::delete_by_feed(&conn, feed.id)?;
-        let feed = feed.delete(&conn)?;
+        // groups, items, revisions and links of the feed are deleted along with it
+        let feed = Feed::get(&conn, id)?.delete(&conn)?;
         println!("Feed deleted!\n{}", feed);
         Ok(())
     }
@@ -178,75 +229,276 @@ impl FeedCommand {
             Feed::get(&conn, id)?
         };
 
-        feed.crawl(state).await?;
+        let result = feed.crawl(state.clone()).await;
+
+        let conn = state.db.get()?;
+        let feed = Feed::get(&conn, id)?;
+        match result {
+            Ok(_) => {
+                feed.record_success(&conn)?;
+                Ok(())
+            }
+            Err(e) => {
+                feed.record_failure(&conn, &e.describe())?;
+                Err(e.into())
+            }
+        }
+    }
+
+    fn status(state: State, id: u32) -> Result<()> {
+        let conn = state.db.get()?;
+        let feed = Feed::get(&conn, id)
+            .with_context(|| anyhow!("Unable to find feed with id = {}", id))?;
+
+        print!("{}", feed);
+        println!(
+            "Last status: {}",
+            feed.last_status
+                .map(|status| status.to_string())
+                .unwrap_or_else(|| "-".to_owned())
+        );
+        println!("Last success: {}", format_time(feed.last_success));
+        println!("Consecutive errors: {}", feed.error_count);
+        if let Some(error) = feed.last_error.as_ref() {
+            println!("Last error: {}", error);
+        }
+        if let Some(interval) = feed.fetch_interval {
+            println!("Crawl interval: {} minutes", interval / 60);
+        }
+        println!("Next crawl: {}", format_time(feed.next_fetch));
         Ok(())
     }
 
-    async fn import(state: State, file: PathBuf) -> Result<()> {
+    fn set_interval(state: State, id: u32, minutes: Option<u32>) -> Result<()> {
+        let conn = state.db.get()?;
+        let feed = Feed::get(&conn, id)
+            .with_context(|| anyhow!("Unable to find feed with id = {}", id))?;
+        let feed = feed.set_fetch_interval(&conn, minutes.map(|m| m.saturating_mul(60)))?;
+        match minutes {
+            Some(minutes) => println!("Feed {} is crawled every {} minutes", feed.id, minutes),
+            None => println!("Feed {} is crawled based on its update frequency", feed.id),
+        }
+        Ok(())
+    }
+
+    fn edit(
+        state: State,
+        id: u32,
+        title: Option<String>,
+        url: Option<String>,
+        site_url: Option<String>,
+    ) -> Result<()> {
+        if title.is_none() && url.is_none() && site_url.is_none() {
+            return Err(anyhow!("Either --title, --url or --site-url is required"));
+        }
+
+        let conn = state.db.get()?;
+        let mut feed = Feed::get(&conn, id)
+            .with_context(|| anyhow!("Unable to find feed with id = {}", id))?;
+        if let Some(url) = url {
+            url::Url::parse(&url).with_context(|| anyhow!("Invalid feed URL '{}'", url))?;
+            if let Some(other) = Feed::get_by_url(&conn, &url)?.filter(|other| other.id != id) {
+                return Err(anyhow!(
+                    "Feed `{}` already exists with id = {}",
+                    url,
+                    other.id
+                ));
+            }
+            feed.url = url;
+        }
+        if let Some(site_url) = site_url {
+            url::Url::parse(&site_url)
+                .with_context(|| anyhow!("Invalid site URL '{}'", site_url))?;
+            feed.site_url = site_url;
+        }
+        if let Some(title) = title {
+            feed.title = title;
+        }
+
+        let feed = feed.update(&conn)?;
+        println!("Feed updated!\n{}", feed);
+        Ok(())
+    }
+
+    async fn import(state: State, file: PathBuf, dry_run: bool) -> Result<()> {
         let imports = opml::from_file(&file)?;
 
-        let imports: Vec<_> = stream::iter(imports)
-            .then(|(group, feeds)| async move {
-                // normalize feeds
-                let feeds = stream::iter(feeds)
-                    .filter_map(|mut feed| async move {
-                        if let Err(e) = feed.update().await {
-                            warn!("failed to update feed {}: {:?}", feed, e);
+        // status, feed URL and details of every feed in the file
+        let mut table = Table::new();
+        table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+        table.set_titles(row!["status", "feed url", "details"]);
+        let mut new_feeds = Vec::new();
+        let mut new_groups: Vec<String> = Vec::new();
+        {
+            let conn = state.db.get()?;
+            for feed in imports.into_iter() {
+                if let Err(e) = feed.validate() {
+                    table.add_row(row!["invalid", feed.url(), e.describe()]);
+                } else if Feed::get_by_url(&conn, feed.url())?.is_some() {
+                    table.add_row(row!["exists", feed.url(), ""]);
+                } else {
+                    for path in feed.groups().iter() {
+                        for depth in 1..=path.len() {
+                            let name = path[..depth].join(" / ");
+                            if !new_groups.contains(&name)
+                                && Group::get_by_path(&conn, &path[..depth])?.is_none()
+                            {
+                                new_groups.push(name);
+                            }
                         }
+                    }
+                    new_feeds.push(feed);
+                }
+            }
+        }
 
-                        if let Err(e) = feed.validate() {
-                            warn!("invalid feed ({}): {:?}", feed, e);
-                            None
-                        } else {
-                            Some(feed)
-                        }
-                    })
-                    .map(Feed::from)
-                    .collect::<Vec<Feed>>()
-                    .await;
+        if dry_run {
+            for feed in new_feeds.iter() {
+                table.add_row(row!["new", feed.url(), format_group_paths(feed.groups())]);
+            }
+            table.printstd();
+            if !new_groups.is_empty() {
+                println!("\nGroups to create: {}", new_groups.join(", "));
+            }
+            return Ok(());
+        }
 
-                (group, feeds)
+        // normalize feeds
+        let new_feeds: Vec<_> = stream::iter(new_feeds)
+            .then(|mut feed| async move {
+                if let Err(e) = feed.update().await {
+                    warn!("failed to update feed {}: {:?}", feed, e);
+                }
+                feed
             })
             .collect()
             .await;
 
         let conn = state.db.get()?;
-        for (group, feeds) in imports.into_iter() {
-            let group = group.and_then(|title| {
-                if let Ok(group) = Group::get_by_name(&conn, &title) {
-                    Some(group)
-                } else {
-                    let group = Group::new(title.clone());
-                    match group.insert(&conn) {
-                        Ok(group) => Some(group),
-                        Err(e) => {
-                            warn!("unable to create group {}: {:?}", title, e);
-                            None
-                        }
-                    }
+        let mut created_groups = 0;
+        'feeds: for imported in new_feeds.into_iter() {
+            let url = imported.url().to_owned();
+            if let Err(e) = imported.validate() {
+                table.add_row(row!["invalid", url, e.describe()]);
+                continue;
+            }
+
+            let paths = imported.groups().to_vec();
+            let mut feed = match Feed::from(imported).insert(&conn) {
+                Err(e) => {
+                    table.add_row(row!["failed", url, e.describe()]);
+                    continue;
                 }
-            });
+                Ok(feed) => feed,
+            };
 
-            for feed in feeds {
-                let feed = match feed.insert(&conn) {
+            for path in paths.iter() {
+                let group = match Self::import_group(&conn, path, &mut created_groups) {
+                    Ok(group) => group,
                     Err(e) => {
-                        warn!("unable to create feed: {:?}", e);
+                        warn!("unable to create group {}: {:?}", path.join(" / "), e);
                         continue;
                     }
-                    Ok(feed) => feed,
                 };
 
-                if let Some(group) = group.as_ref() {
-                    if let Err(e) = group.add_feed(&conn, feed) {
-                        warn!("unable to add feed to group {:?}: {:?}", group, e);
-                        continue;
+                feed = match group.add_feed(&conn, feed) {
+                    Ok(feed) => feed,
+                    Err(e) => {
+                        let details = format!(
+                            "unable to add to group {}: {}",
+                            path.join(" / "),
+                            e.describe()
+                        );
+                        table.add_row(row!["failed", url, details]);
+                        continue 'feeds;
                     }
-                }
+                };
             }
+            table.add_row(row!["added", url, format_group_paths(&paths)]);
         }
 
         info!("import completed.");
 
+        table.printstd();
+        let count = |status: &str| {
+            table
+                .row_iter()
+                .filter(|row| row.get_cell(0).map(|cell| cell.get_content()) == Some(status.into()))
+                .count()
+        };
+        let mut summary = Table::new();
+        summary.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+        summary.set_titles(row![
+            "added",
+            "already existing",
+            "invalid",
+            "failed",
+            "groups created"
+        ]);
+        summary.add_row(row![
+            count("added"),
+            count("exists"),
+            count("invalid"),
+            count("failed"),
+            created_groups
+        ]);
+        println!();
+        summary.printstd();
+
+        Ok(())
+    }
+
+    /// Finds the group at the end of `path`. Groups of the path which don't exist yet are
+    /// created, nested in the group before them.
+    fn import_group(
+        conn: &rusqlite::Connection,
+        path: &[String],
+        created: &mut usize,
+    ) -> Result<Group> {
+        let mut parent: Option<Group> = None;
+        for title in path.iter() {
+            let parent_id = parent.map(|parent| parent.id);
+            let group = match Group::get_child(conn, parent_id, title)? {
+                Some(group) => group,
+                None => {
+                    let group = Group {
+                        parent_id,
+                        ..Group::new(title.clone())
+                    }
+                    .insert(conn)?;
+                    *created += 1;
+                    group
+                }
+            };
+            parent = Some(group);
+        }
+        parent.ok_or_else(|| anyhow!("Group path is empty"))
+    }
+
+    fn export(state: State, file: Option<PathBuf>) -> Result<()> {
+        let conn = state.db.get()?;
+        let mut grouped = std::collections::HashSet::new();
+        let mut groups = Vec::new();
+        for group in Group::all(&conn)? {
+            let feeds = group.get_feeds(&conn)?;
+            grouped.extend(feeds.iter().map(|feed| feed.id));
+            groups.push((group, feeds));
+        }
+        let sparks = Feed::all(&conn)?
+            .into_iter()
+            .filter(|feed| !grouped.contains(&feed.id))
+            .collect::<Vec<_>>();
+
+        match file {
+            Some(file) => {
+                let file = std::fs::File::create(&file)
+                    .with_context(|| anyhow!("Unable to create {}", file.display()))?;
+                let mut writer = io::BufWriter::new(file);
+                opml::to_writer(&mut writer, &groups, &sparks)?;
+                writer.flush()?;
+            }
+            None => opml::to_writer(io::stdout().lock(), &groups, &sparks)?,
+        }
         Ok(())
     }
 
@@ -256,7 +508,16 @@ impl FeedCommand {
             Self::Add { url, group } => Self::add(state, url, group).await,
             Self::Delete { id } => Self::delete(state, id),
             Self::Crawl { id } => Self::crawl(state, id).await,
-            Self::Import { file } => Self::import(state, file).await,
+            Self::Status { id } => Self::status(state, id),
+            Self::SetInterval { id, minutes } => Self::set_interval(state, id, minutes),
+            Self::Edit {
+                id,
+                title,
+                url,
+                site_url,
+            } => Self::edit(state, id, title, url, site_url),
+            Self::Import { file, dry_run } => Self::import(state, file, dry_run).await,
+            Self::Export { file } => Self::export(state, file),
         }
     }
 }
@@ -267,11 +528,29 @@ pub enum GroupCommand {
     List,
 
     /// Adds a group
-    Add { name: String },
+    Add {
+        name: String,
+        #[structopt(short = "p", long = "parent")]
+        /// Nests the new group in this group
+        parent: Option<String>,
+    },
 
     /// Adds a feed to group
     AddFeed { id: u32, group: String },
 
+    /// Removes a feed from group without deleting it
+    RemoveFeed { id: u32, group: String },
+
+    /// Renames a group
+    Rename { name: String, new_name: String },
+
+    /// Nests a group in another one, or moves it to the top level when no parent is given
+    Move {
+        name: String,
+        #[structopt(short = "p", long = "parent")]
+        parent: Option<String>,
+    },
+
     /// Deletes a group
     Delete { name: String },
 
@@ -280,37 +559,44 @@ pub enum GroupCommand {
 }
 
 impl GroupCommand {
+    fn get(conn: &rusqlite::Connection, name: &str) -> Result<Group> {
+        Group::get_by_name(conn, name).with_context(|| anyhow!("Unable to find group '{}'", name))
+    }
+
     fn list(state: State) -> Result<()> {
-        let groups = {
+        let (groups, unread) = {
             let conn = state.db.get()?;
-            Group::all(&conn)?
+            (Group::all_with_paths(&conn)?, Group::unread_counts(&conn)?)
         };
         let mut table = Table::new();
         table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-        table.set_titles(row!["id", "name"]);
+        table.set_titles(row!["id", "name", "unread"]);
 
         for group in groups.into_iter() {
-            table.add_row(row![group.id, group.title]);
+            let unread = unread.get(&group.id).copied().unwrap_or_default();
+            table.add_row(row![group.id, group.title, unread]);
         }
 
         table.printstd();
         Ok(())
     }
 
-    fn add(state: State, name: String) -> Result<()> {
+    fn add(state: State, name: String, parent: Option<String>) -> Result<()> {
         let conn = state.db.get()?;
-        let group = Group::new(name.clone());
-        group
-            .insert(&conn)
-            .with_context(|| anyhow!("Unable to create group '{}'.", name))?;
+        let parent = parent.map(|parent| Self::get(&conn, &parent)).transpose()?;
+        Group {
+            parent_id: parent.map(|parent| parent.id),
+            ..Group::new(name.clone())
+        }
+        .insert(&conn)
+        .with_context(|| anyhow!("Unable to create group '{}'.", name))?;
         println!("Group '{}' added.", name);
         Ok(())
     }
 
     fn add_feed(state: State, feed_id: u32, group: String) -> Result<()> {
         let conn = state.db.get()?;
-        let group = Group::get_by_name(&conn, &group)
-            .with_context(|| anyhow!("Unable to find group '{}'", group))?;
+        let group = Self::get(&conn, &group)?;
         let feed = Feed::get(&conn, feed_id)
             .with_context(|| anyhow!("Unable to find feed with id = {}", feed_id))?;
         // if let Ok((_, group_id)) = FeedGroup::get_by_feed(&conn, feed_id) {}
@@ -318,15 +604,45 @@ impl GroupCommand {
         Ok(())
     }
 
+    fn remove_feed(state: State, feed_id: u32, group: String) -> Result<()> {
+        let conn = state.db.get()?;
+        let group = Self::get(&conn, &group)?;
+        let feed = Feed::get(&conn, feed_id)
+            .with_context(|| anyhow!("Unable to find feed with id = {}", feed_id))?;
+        let feed = group.remove_feed(&conn, feed)?;
+        println!("Feed {} removed from group {}", feed.id, group.title);
+        Ok(())
+    }
+
+    fn rename(state: State, name: String, new_name: String) -> Result<()> {
+        let conn = state.db.get()?;
+        let group = Self::get(&conn, &name)?.rename(&conn, new_name)?;
+        println!("Group '{}' renamed to '{}'.", name, group.title);
+        Ok(())
+    }
+
+    fn move_group(state: State, name: String, parent: Option<String>) -> Result<()> {
+        let conn = state.db.get()?;
+        let group = Self::get(&conn, &name)?;
+        let parent = parent.map(|parent| Self::get(&conn, &parent)).transpose()?;
+        let group = group.set_parent(&conn, parent.as_ref())?;
+        match parent {
+            Some(parent) => println!("Group '{}' moved into '{}'.", group.title, parent.title),
+            None => println!("Group '{}' moved to the top level.", group.title),
+        }
+        Ok(())
+    }
+
     fn delete(state: State, group: String) -> Result<()> {
         let conn = state.db.get()?;
-        let group = Group::get_by_name(&conn, &group)
-            .with_context(|| anyhow!("Unable to find group '{}'", group))?;
+        let group = Self::get(&conn, &group)?;
         if let Ok(feed_groups) = FeedGroup::get_by_group(&conn, group.id) {
             if feed_groups.feed_ids.len() != 0 {
                 println!("Warning: there are still feeds belong to this group");
             }
-            feed_groups.delete(&conn)?;
+        }
+        if !group.children(&conn)?.is_empty() {
+            println!("Warning: subgroups of this group are moved to the top level");
         }
         let group = group.delete(&conn)?;
         println!("Group {} deleted", group.title);
@@ -335,10 +651,12 @@ impl GroupCommand {
 
     fn show(state: State, group: String) -> Result<()> {
         let conn = state.db.get()?;
-        let group = Group::get_by_name(&conn, &group)
-            .with_context(|| anyhow!("Unable to find group '{}'", group))?;
+        let group = Self::get(&conn, &group)?;
         let feeds = group.get_feeds(&conn)?;
         println!("Group {}:\n", group.title);
+        for subgroup in group.children(&conn)?.iter() {
+            println!("Subgroup: {}\n", subgroup.title);
+        }
         for feed in feeds.iter() {
             println!("{}", feed);
         }
@@ -348,14 +666,359 @@ impl GroupCommand {
     async fn run(self, state: State) -> Result<()> {
         match self {
             Self::List => Self::list(state),
-            Self::Add { name } => Self::add(state, name),
+            Self::Add { name, parent } => Self::add(state, name, parent),
             Self::AddFeed { id, group } => Self::add_feed(state, id, group),
+            Self::RemoveFeed { id, group } => Self::remove_feed(state, id, group),
+            Self::Rename { name, new_name } => Self::rename(state, name, new_name),
+            Self::Move { name, parent } => Self::move_group(state, name, parent),
             Self::Delete { name } => Self::delete(state, name),
             Self::Show { name } => Self::show(state, name),
         }
     }
 }
 
+/// Parses either a RFC 3339 timestamp or a plain `YYYY-MM-DD` date (midnight UTC).
+fn parse_time(s: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let date = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .with_context(|| anyhow!("Invalid date '{}', expected YYYY-MM-DD or RFC 3339", s))?;
+    Ok(DateTime::from_utc(date.and_hms(0, 0, 0), Utc))
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ItemFilterOptions {
+    #[structopt(short = "f", long = "feed")]
+    /// Only shows items of the feed with this id
+    feed: Option<u32>,
+
+    #[structopt(short = "g", long = "group")]
+    /// Only shows items of feeds in this group
+    group: Option<String>,
+
+    #[structopt(short = "u", long = "unread")]
+    /// Only shows unread items
+    unread: bool,
+
+    #[structopt(short = "s", long = "saved")]
+    /// Only shows saved items
+    saved: bool,
+
+    #[structopt(long = "since", parse(try_from_str = parse_time))]
+    /// Only shows items published on or after this date (YYYY-MM-DD or RFC 3339)
+    since: Option<DateTime<Utc>>,
+}
+
+impl ItemFilterOptions {
+    fn filter(self, conn: &rusqlite::Connection) -> Result<ItemFilter> {
+        let group_id = match self.group {
+            Some(group) => Some(
+                Group::get_by_name(conn, &group)
+                    .with_context(|| anyhow!("Unable to find group '{}'", group))?
+                    .id,
+            ),
+            None => None,
+        };
+        Ok(ItemFilter {
+            feed_id: self.feed,
+            group_id,
+            unread: self.unread,
+            saved: self.saved,
+            since: self.since,
+        })
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ItemCommand {
+    /// Lists items, newest first
+    List {
+        #[structopt(flatten)]
+        filter: ItemFilterOptions,
+        #[structopt(short = "n", long = "limit", default_value = "20")]
+        limit: u32,
+    },
+
+    /// Shows an item along with its content as text
+    Show { id: u32 },
+
+    /// Marks items as read
+    Read {
+        #[structopt(required = true)]
+        ids: Vec<u32>,
+    },
+
+    /// Marks items as unread
+    Unread {
+        #[structopt(required = true)]
+        ids: Vec<u32>,
+    },
+
+    /// Saves items
+    Save {
+        #[structopt(required = true)]
+        ids: Vec<u32>,
+    },
+
+    /// Unsaves items
+    Unsave {
+        #[structopt(required = true)]
+        ids: Vec<u32>,
+    },
+
+    /// Shows what changed in an item since its previous revision
+    Diff { id: u32 },
+
+    /// Searches items by their title, author and content
+    Search {
+        query: String,
+        #[structopt(flatten)]
+        filter: ItemFilterOptions,
+        #[structopt(short = "n", long = "limit", default_value = "20")]
+        limit: u32,
+    },
+}
+
+impl ItemCommand {
+    fn diff(state: State, id: u32) -> Result<()> {
+        let conn = state.db.get()?;
+        let item = Item::get(&conn, id)
+            .with_context(|| anyhow!("Unable to find item with id = {}", id))?;
+        let previous = match ItemRevision::by_item(&conn, item.id)?.pop() {
+            Some(previous) => previous,
+            None => {
+                println!("Item {} has not changed since it was first seen", item.id);
+                return Ok(());
+            }
+        };
+
+        println!(
+            "Item {} was updated on {}",
+            item.id,
+            format_time(Some(previous.replaced_on_time))
+        );
+        if previous.title != item.title {
+            println!("- title: {}", previous.title);
+            println!("+ title: {}", item.title);
+        }
+        if previous.url != item.url {
+            println!("- url: {}", previous.url);
+            println!("+ url: {}", item.url);
+        }
+
+        let changeset = Changeset::new(&previous.html, &item.html, "\n");
+        for diff in changeset.diffs.iter() {
+            let (prefix, text) = match diff {
+                Difference::Same(text) => (" ", text),
+                Difference::Add(text) => ("+", text),
+                Difference::Rem(text) => ("-", text),
+            };
+            for line in text.lines() {
+                println!("{} {}", prefix, line);
+            }
+        }
+        Ok(())
+    }
+
+    fn print_items(items: &[Item]) {
+        if items.is_empty() {
+            println!("No items found.");
+        }
+        for item in items.iter() {
+            let status = match (item.is_read, item.is_saved) {
+                (false, false) => " (unread)",
+                (false, true) => " (unread, saved)",
+                (true, false) => "",
+                (true, true) => " (saved)",
+            };
+            println!(
+                "{} [{}] {}{}\n    {}",
+                item.id,
+                format_time(Some(item.created_on_time)),
+                item.title,
+                status,
+                item.url
+            );
+        }
+    }
+
+    fn list(state: State, filter: ItemFilterOptions, limit: u32) -> Result<()> {
+        let conn = state.db.get()?;
+        let filter = filter.filter(&conn)?;
+        Self::print_items(&Item::list(&conn, &filter, limit)?);
+        Ok(())
+    }
+
+    fn show(state: State, id: u32) -> Result<()> {
+        let conn = state.db.get()?;
+        let item = Item::get(&conn, id)
+            .with_context(|| anyhow!("Unable to find item with id = {}", id))?;
+        let feed = Feed::get(&conn, item.feed_id)?;
+
+        println!("Title: {}", item.title);
+        println!("Feed: {}", feed.title);
+        if !item.author.is_empty() {
+            println!("Author: {}", item.author);
+        }
+        println!("URL: {}", item.url);
+        println!("Date: {}", format_time(Some(item.created_on_time)));
+        println!(
+            "Status: {}{}",
+            if item.is_read { "read" } else { "unread" },
+            if item.is_saved { ", saved" } else { "" }
+        );
+        println!();
+        println!("{}", crate::find::html_to_text(&item.html));
+        Ok(())
+    }
+
+    fn mark(
+        state: State,
+        ids: Vec<u32>,
+        mark: fn(Item, &rusqlite::Connection) -> crate::error::Result<Item>,
+        status: &str,
+    ) -> Result<()> {
+        let conn = state.db.get()?;
+        for id in ids {
+            let item = Item::get(&conn, id)
+                .with_context(|| anyhow!("Unable to find item with id = {}", id))?;
+            mark(item, &conn)?;
+            println!("Item {} marked as {}", id, status);
+        }
+        Ok(())
+    }
+
+    fn search(state: State, query: String, filter: ItemFilterOptions, limit: u32) -> Result<()> {
+        let conn = state.db.get()?;
+        let filter = filter.filter(&conn)?;
+        Self::print_items(&Item::search(&conn, &query, &filter, limit)?);
+        Ok(())
+    }
+
+    async fn run(self, state: State) -> Result<()> {
+        match self {
+            Self::List { filter, limit } => Self::list(state, filter, limit),
+            Self::Show { id } => Self::show(state, id),
+            Self::Read { ids } => Self::mark(state, ids, Item::read, "read"),
+            Self::Unread { ids } => Self::mark(state, ids, Item::mark_unread, "unread"),
+            Self::Save { ids } => Self::mark(state, ids, Item::save, "saved"),
+            Self::Unsave { ids } => Self::mark(state, ids, Item::unsave, "unsaved"),
+            Self::Diff { id } => Self::diff(state, id),
+            Self::Search {
+                query,
+                filter,
+                limit,
+            } => Self::search(state, query, filter, limit),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RetentionConfig {
+    #[structopt(long = "max-age")]
+    /// Purges items older than this (unit: days)
+    max_age: Option<u32>,
+
+    #[structopt(long = "max-items")]
+    /// Keeps only this many of the newest items of each feed
+    max_items: Option<u32>,
+
+    #[structopt(long = "purge-saved")]
+    /// Purges saved items as well, which are kept by default
+    purge_saved: bool,
+}
+
+impl RetentionConfig {
+    fn retention(&self) -> model::Retention {
+        model::Retention {
+            max_age: self.max_age.map(|days| chrono::Duration::days(days as i64)),
+            max_items_per_feed: self.max_items,
+            keep_saved: !self.purge_saved,
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum DbCommand {
+    /// Applies pending schema migrations
+    Migrate,
+
+    /// Shows applied and pending schema migrations
+    Status,
+
+    /// Deletes items according to the retention settings
+    Purge {
+        #[structopt(long = "dry-run")]
+        /// Only shows how many items would be deleted
+        dry_run: bool,
+
+        #[structopt(flatten)]
+        retention: RetentionConfig,
+    },
+}
+
+impl DbCommand {
+    fn migrate(state: State) -> Result<()> {
+        let mut conn = state.db.get()?;
+        let applied = model::migrate(&mut conn)?;
+        if applied.is_empty() {
+            println!("Database is up to date.");
+        }
+        for migration in applied.iter() {
+            println!(
+                "Applied migration {}: {}",
+                migration.version, migration.description
+            );
+        }
+        Ok(())
+    }
+
+    fn status(state: State) -> Result<()> {
+        let conn = state.db.get()?;
+        let applied = model::applied_migrations(&conn)?;
+        println!("Schema version: {}", model::schema_version(&conn)?);
+
+        for migration in model::MIGRATIONS.iter() {
+            let applied_on = applied
+                .iter()
+                .find(|(version, _)| *version == migration.version)
+                .map(|(_, time)| format_time(Some(*time)))
+                .unwrap_or_else(|| "pending".to_owned());
+            println!(
+                "{}: {} ({})",
+                migration.version, migration.description, applied_on
+            );
+        }
+        Ok(())
+    }
+
+    fn purge(state: State, dry_run: bool, retention: RetentionConfig) -> Result<()> {
+        let retention = retention.retention();
+        if !retention.is_enabled() {
+            return Err(anyhow!("Either --max-age or --max-items is required"));
+        }
+
+        let mut conn = state.db.get()?;
+        if dry_run {
+            let expired = retention.expired_items(&conn, Utc::now())?;
+            println!("{} items would be purged.", expired.len());
+        } else {
+            let purged = retention.purge(&mut conn, Utc::now())?;
+            println!("{} items purged.", purged);
+        }
+        Ok(())
+    }
+
+    async fn run(self, state: State) -> Result<()> {
+        match self {
+            Self::Migrate => Self::migrate(state),
+            Self::Status => Self::status(state),
+            Self::Purge { dry_run, retention } => Self::purge(state, dry_run, retention),
+        }
+    }
+}
+
 #[derive(Debug, StructOpt)]
 pub struct ServerConfig {
     #[structopt(short = "H", long = "host", default_value = "127.0.0.1")]
@@ -375,8 +1038,32 @@ pub struct ServerConfig {
     password: Option<String>,
 
     #[structopt(short = "i", long = "interval", default_value = "30")]
-    /// Specifies crawl interval (unit: minutes)
+    /// Specifies default and minimum crawl interval (unit: minutes)
     interval: u32,
+
+    #[structopt(
+        long = "concurrency",
+        default_value = default_str(Crawler::DEFAULT_CONCURRENCY)
+    )]
+    /// Specifies how many feeds can be crawled at the same time
+    concurrency: usize,
+
+    #[structopt(
+        long = "host-concurrency",
+        default_value = default_str(Crawler::DEFAULT_HOST_CONCURRENCY)
+    )]
+    /// Specifies how many requests can be sent to the same host at the same time
+    host_concurrency: usize,
+
+    #[structopt(
+        long = "host-delay",
+        default_value = default_str(Crawler::DEFAULT_HOST_DELAY_SECS)
+    )]
+    /// Specifies minimum delay between two requests to the same host (unit: seconds)
+    host_delay: u64,
+
+    #[structopt(flatten)]
+    retention: RetentionConfig,
 }
 
 #[derive(Debug, StructOpt)]
@@ -384,7 +1071,14 @@ pub enum SubCommand {
     /// Manages feeds
     Feed(FeedCommand),
     /// Manages group
+    ///
+    /// Groups are given by their title, or by their path such as `Parent / Child` when several
+    /// groups share a title.
     Group(GroupCommand),
+    /// Manages items
+    Item(ItemCommand),
+    /// Manages database schema
+    Db(DbCommand),
     /// Starts web server
     Server(ServerConfig),
 }
@@ -400,6 +1094,38 @@ pub struct Options {
     )]
     database: PathBuf,
 
+    #[structopt(
+        long = "journal-mode",
+        default_value = model::DatabaseConfig::DEFAULT_JOURNAL_MODE,
+        possible_values = &["DELETE", "TRUNCATE", "PERSIST", "MEMORY", "WAL", "OFF"],
+        case_insensitive = true
+    )]
+    /// Specifies SQLite journal mode
+    journal_mode: String,
+
+    #[structopt(
+        long = "synchronous",
+        default_value = model::DatabaseConfig::DEFAULT_SYNCHRONOUS,
+        possible_values = &["OFF", "NORMAL", "FULL", "EXTRA"],
+        case_insensitive = true
+    )]
+    /// Specifies SQLite synchronous mode
+    synchronous: String,
+
+    #[structopt(
+        long = "busy-timeout",
+        default_value = default_str(model::DatabaseConfig::DEFAULT_BUSY_TIMEOUT_MS)
+    )]
+    /// Specifies how long to wait for a locked database (unit: milliseconds)
+    busy_timeout: u64,
+
+    #[structopt(
+        long = "cache-size",
+        default_value = default_str(model::DatabaseConfig::DEFAULT_CACHE_SIZE_KIB)
+    )]
+    /// Specifies SQLite page cache size of each connection (unit: KiB)
+    cache_size: u32,
+
     #[structopt(long)]
     debug: bool,
 
@@ -416,18 +1142,37 @@ impl Options {
         }
 
         let app = crate::api::make_app(state.clone());
+        let purger = crate::crawler::purgeloop(state.clone(), config.retention.retention());
         let crawl_interval = ((config.interval) * 60) as u64;
-        let crwaler = crate::crawler::Crawler::new(state, crawl_interval);
-        let (web, crawl) = app
+        let crwaler = Crawler::new(state, crawl_interval)
+            .set_concurrency(config.concurrency)
+            .set_host_limit(
+                config.host_concurrency,
+                std::time::Duration::from_secs(config.host_delay),
+            );
+        let ((web, crawl), purge) = app
             .listen(format!("{}:{}", config.host, config.port))
             .join(crwaler.runloop())
+            .join(purger)
             .await;
-        (web?, crawl?);
+        (web?, crawl?, purge?);
         Ok(())
     }
 
     pub async fn run(self) -> Result<()> {
-        let pool = crate::model::get_pool(&self.database)?;
+        let config = model::DatabaseConfig {
+            journal_mode: self.journal_mode,
+            synchronous: self.synchronous,
+            busy_timeout: std::time::Duration::from_millis(self.busy_timeout),
+            cache_size: -(self.cache_size as i64),
+        };
+        // these inspect and migrate the schema themselves
+        let pool = match self.command {
+            SubCommand::Db(DbCommand::Migrate) | SubCommand::Db(DbCommand::Status) => {
+                model::open_pool(&self.database, config)?
+            }
+            _ => model::get_pool(&self.database, config)?,
+        };
         let state = crate::state::State::new(pool);
 
         if self.debug {
@@ -439,6 +1184,8 @@ impl Options {
         match self.command {
             SubCommand::Feed(cmd) => cmd.run(state).await,
             SubCommand::Group(cmd) => cmd.run(state).await,
+            SubCommand::Item(cmd) => cmd.run(state).await,
+            SubCommand::Db(cmd) => cmd.run(state).await,
             SubCommand::Server(config) => Self::server(state, config).await,
         }
     }
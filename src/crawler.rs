@@ -1,47 +1,351 @@
 use crate::error::Result;
-use crate::model::{Feed, ModelExt};
+use crate::model::{Feed, ModelExt, Retention};
 use crate::state::State;
-use async_std::stream;
-use async_std::stream::StreamExt;
+use async_std::sync::{channel, Mutex, Receiver, Sender};
 use async_std::task;
+use chrono::Utc;
 use futures::future::join_all;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Upper bound of sleeping between two checks, so newly added feeds are picked up quickly.
+const POLL_INTERVAL_SECS: u64 = 60;
+
+/// How often items are checked against the retention policy.
+const PURGE_INTERVAL_SECS: u64 = 60 * 60;
+
+/// Counting semaphore backed by a bounded channel holding one token per permit.
+#[derive(Clone)]
+struct Slots {
+    tx: Sender<()>,
+    rx: Receiver<()>,
+}
+
+impl Slots {
+    fn new(permits: usize) -> Self {
+        let permits = permits.max(1);
+        let (tx, rx) = channel(permits);
+        for _ in 0..permits {
+            let _ = tx.try_send(());
+        }
+        Slots { tx, rx }
+    }
+
+    async fn acquire(&self) -> SlotGuard {
+        let _ = self.rx.recv().await;
+        SlotGuard(self.tx.clone())
+    }
+}
+
+/// Gives the permit back when dropped.
+struct SlotGuard(Sender<()>);
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        let _ = self.0.try_send(());
+    }
+}
+
+/// Politeness state of a single host.
+struct Host {
+    slots: Slots,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl Host {
+    fn new(permits: usize) -> Self {
+        Host {
+            slots: Slots::new(permits),
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// Waits until at least `delay` has passed since the previous request to this host.
+    async fn wait_turn(&self, delay: Duration) {
+        let mut last_request = self.last_request.lock().await;
+        if let Some(elapsed) = last_request.map(|last| last.elapsed()) {
+            if elapsed < delay {
+                task::sleep(delay - elapsed).await;
+            }
+        }
+        *last_request = Some(Instant::now());
+    }
+
+    /// Waits until a request can be sent to this host: both a slot of this host and one of
+    /// `slots` are held, and `delay` has passed since the previous request. The delay is
+    /// waited for last, so that it's measured from when requests are actually sent.
+    async fn reserve(&self, slots: &Slots, delay: Duration) -> (SlotGuard, SlotGuard) {
+        let host_slot = self.slots.acquire().await;
+        let slot = slots.acquire().await;
+        self.wait_turn(delay).await;
+        (host_slot, slot)
+    }
+}
 
 pub struct Crawler {
     state: State,
     interval_secs: u64,
+    slots: Slots,
+    host_concurrency: usize,
+    host_delay: Duration,
+    hosts: std::sync::Mutex<HashMap<String, Arc<Host>>>,
 }
 
 impl Crawler {
+    pub const DEFAULT_CONCURRENCY: usize = 16;
+    pub const DEFAULT_HOST_CONCURRENCY: usize = 2;
+    pub const DEFAULT_HOST_DELAY_SECS: u64 = 1;
+
     pub fn new(state: State, interval_secs: u64) -> Self {
         Crawler {
             state,
             interval_secs,
+            slots: Slots::new(Self::DEFAULT_CONCURRENCY),
+            host_concurrency: Self::DEFAULT_HOST_CONCURRENCY,
+            host_delay: Duration::from_secs(Self::DEFAULT_HOST_DELAY_SECS),
+            hosts: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Limits how many feeds are crawled at the same time.
+    pub fn set_concurrency(mut self, concurrency: usize) -> Self {
+        self.slots = Slots::new(concurrency);
+        self
+    }
+
+    /// Limits how many requests are sent to the same host at the same time, and how long to
+    /// wait between two of them.
+    pub fn set_host_limit(mut self, concurrency: usize, delay: Duration) -> Self {
+        self.host_concurrency = concurrency;
+        self.host_delay = delay;
+        self
+    }
+
+    fn host(&self, url: &str) -> Arc<Host> {
+        let name = url::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_owned()))
+            .unwrap_or_else(|| url.to_owned());
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts
+            .entry(name)
+            .or_insert_with(|| Arc::new(Host::new(self.host_concurrency)))
+            .clone()
+    }
+
+    async fn crawl_feed(state: State, feed: Feed, default_interval: u32) -> Result<()> {
+        let id = feed.id;
+        let result = feed.crawl(state.clone()).await;
+
+        let feed = {
+            let conn = state.db.get()?;
+            let feed = Feed::get(&conn, id)?;
+            let feed = match result.as_ref() {
+                Ok(_) => feed.record_success(&conn)?,
+                Err(e) => feed.record_failure(&conn, &e.describe())?,
+            };
+            feed.schedule(&conn, default_interval)?
+        };
+        result?;
+
+        if feed.needs_favicon(Utc::now()) {
+            feed.update_favicon(state).await?;
         }
+        Ok(())
     }
 
+    /// Crawls every feed that is due.
     async fn crawl(&self) -> Result<()> {
         let feeds = {
             let conn = self.state.db.get()?;
-            Feed::all(&conn)?
+            Feed::due(&conn, Utc::now())?
         };
 
         let _ = join_all(feeds.into_iter().map(|feed| {
             let state = self.state.clone();
-            task::spawn(async move { feed.crawl(state).await })
+            let default_interval = self.interval_secs as u32;
+            let slots = self.slots.clone();
+            let host = self.host(&feed.url);
+            let host_delay = self.host_delay;
+            task::spawn(async move {
+                let _slots = host.reserve(&slots, host_delay).await;
+                Self::crawl_feed(state, feed, default_interval).await
+            })
         }))
         .await;
         Ok(())
     }
 
+    /// Time to sleep until the next feed is due.
+    fn next_wakeup(&self) -> Result<Duration> {
+        let next = {
+            let conn = self.state.db.get()?;
+            Feed::next_due(&conn)?
+        };
+        let secs = next
+            .map(|next| next.signed_duration_since(Utc::now()).num_seconds().max(1) as u64)
+            .unwrap_or(POLL_INTERVAL_SECS);
+        Ok(Duration::from_secs(secs.min(POLL_INTERVAL_SECS)))
+    }
+
     pub async fn runloop(self) -> Result<()> {
-        let mut interval = stream::interval(Duration::from_secs(self.interval_secs));
-        while let Some(_) = interval.next().await {
-            match self.crawl().await {
-                Ok(_) => (),
-                Err(e) => eprintln!("error: {:?}", e),
+        loop {
+            if let Err(e) = self.crawl().await {
+                eprintln!("error: {:?}", e);
             }
+            let wakeup = self.next_wakeup().unwrap_or_else(|e| {
+                eprintln!("error: {:?}", e);
+                Duration::from_secs(POLL_INTERVAL_SECS)
+            });
+            task::sleep(wakeup).await;
         }
-        Ok(())
+    }
+}
+
+/// Purges expired items periodically. Returns right away when `retention` is not enabled.
+pub async fn purgeloop(state: State, retention: Retention) -> Result<()> {
+    if !retention.is_enabled() {
+        return Ok(());
+    }
+
+    loop {
+        let purged = state
+            .db
+            .get()
+            .map_err(Into::into)
+            .and_then(|mut conn| retention.purge(&mut conn, Utc::now()));
+        match purged {
+            Ok(0) => {}
+            Ok(count) => log::info!("purged {} expired items", count),
+            Err(e) => eprintln!("error: {:?}", e),
+        }
+        task::sleep(Duration::from_secs(PURGE_INTERVAL_SECS)).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_slots() {
+        task::block_on(async {
+            let slots = Slots::new(2);
+            let first = slots.acquire().await;
+            let _second = slots.acquire().await;
+            let third = async_std::future::timeout(Duration::from_millis(50), slots.acquire());
+            assert!(third.await.is_err());
+
+            drop(first);
+            let third = async_std::future::timeout(Duration::from_millis(50), slots.acquire());
+            assert!(third.await.is_ok());
+
+            let slots = Slots::new(0);
+            let _only = slots.acquire().await;
+            let second = async_std::future::timeout(Duration::from_millis(50), slots.acquire());
+            assert!(second.await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_host_delay() {
+        task::block_on(async {
+            let delay = Duration::from_millis(100);
+            let host = Host::new(2);
+
+            let start = Instant::now();
+            host.wait_turn(delay).await;
+            assert!(start.elapsed() < delay);
+            host.wait_turn(delay).await;
+            assert!(start.elapsed() >= delay);
+            host.wait_turn(delay).await;
+            assert!(start.elapsed() >= 2 * delay);
+        });
+    }
+
+    /// Sends `requests` fake requests to each of `hosts` through `reserve`, and returns the
+    /// highest number of requests in flight overall, and to a single host.
+    fn max_in_flight(
+        hosts: usize,
+        requests: usize,
+        slots: Slots,
+        permits: usize,
+    ) -> (usize, usize) {
+        let total = Arc::new(AtomicUsize::new(0));
+        let max_total = Arc::new(AtomicUsize::new(0));
+        let max_host = Arc::new(AtomicUsize::new(0));
+        let tasks = (0..hosts).flat_map(|_| {
+            let host = Arc::new(Host::new(permits));
+            let in_flight = Arc::new(AtomicUsize::new(0));
+            let (slots, total, max_total, max_host) = (
+                slots.clone(),
+                total.clone(),
+                max_total.clone(),
+                max_host.clone(),
+            );
+            (0..requests).map(move |_| {
+                let (host, in_flight, slots) = (host.clone(), in_flight.clone(), slots.clone());
+                let (total, max_total, max_host) =
+                    (total.clone(), max_total.clone(), max_host.clone());
+                task::spawn(async move {
+                    let _slots = host.reserve(&slots, Duration::from_millis(0)).await;
+                    max_host.fetch_max(
+                        in_flight.fetch_add(1, Ordering::SeqCst) + 1,
+                        Ordering::SeqCst,
+                    );
+                    max_total.fetch_max(total.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                    task::sleep(Duration::from_millis(20)).await;
+                    total.fetch_sub(1, Ordering::SeqCst);
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+        });
+        task::block_on(join_all(tasks.collect::<Vec<_>>()));
+        (
+            max_total.load(Ordering::SeqCst),
+            max_host.load(Ordering::SeqCst),
+        )
+    }
+
+    #[test]
+    fn test_reserve_concurrency() {
+        assert_eq!(max_in_flight(1, 6, Slots::new(4), 2), (2, 2));
+        assert_eq!(max_in_flight(4, 3, Slots::new(3), 2), (3, 2));
+        assert_eq!(max_in_flight(3, 3, Slots::new(16), 1), (3, 1));
+    }
+
+    #[test]
+    fn test_reserve_delay() {
+        let delay = Duration::from_millis(50);
+        let slots = Slots::new(1);
+        let host = Arc::new(Host::new(4));
+        let start = Instant::now();
+
+        // The only global slot is taken while the host is ready, so the host delay has to
+        // start over once it's free again.
+        let busy = task::block_on(slots.acquire());
+        let first = {
+            let (host, slots) = (host.clone(), slots.clone());
+            task::spawn(async move {
+                let _slots = host.reserve(&slots, delay).await;
+                Instant::now()
+            })
+        };
+        let second = {
+            let (host, slots) = (host.clone(), slots.clone());
+            task::spawn(async move {
+                let _slots = host.reserve(&slots, delay).await;
+                Instant::now()
+            })
+        };
+        task::block_on(task::sleep(2 * delay));
+        drop(busy);
+
+        let mut sent = task::block_on(join_all(vec![first, second]));
+        sent.sort();
+        assert!(sent[0] - start >= 2 * delay);
+        assert!(sent[1] - sent[0] >= delay);
     }
 }
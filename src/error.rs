@@ -61,6 +61,17 @@ impl Error {
     pub fn message(msg: String) -> Self {
         Error::Message(msg)
     }
+
+    /// Renders this error along with all of its sources, e.g. for storing it.
+    pub fn describe(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(e) = source {
+            message.push_str(&format!(": {}", e));
+            source = e.source();
+        }
+        message
+    }
 }
 
 impl From<(quick_xml::Error, usize)> for Error {
@@ -1,5 +1,5 @@
 /// Finds Feed urls on a web page.
-use quick_xml::events::Event;
+use quick_xml::events::{BytesText, Event};
 use quick_xml::Reader;
 use std::io::BufRead;
 
@@ -7,6 +7,24 @@ use crate::error::Result;
 
 /// Parses HTML page to find `<link rel="alternate" />` and extract hrefs.
 pub fn find_rel_alternates<B: BufRead>(reader: B) -> Result<Vec<String>> {
+    find_link_hrefs(reader, |rel| rel == b"alternate")
+}
+
+/// Parses HTML page to find `<link rel="icon" />` (as well as `rel="shortcut icon"`) and extract
+/// hrefs.
+pub fn find_rel_icons<B: BufRead>(reader: B) -> Result<Vec<String>> {
+    find_link_hrefs(reader, |rel| {
+        rel.split(|&c| c == b' ')
+            .any(|token| token.eq_ignore_ascii_case(b"icon"))
+    })
+}
+
+/// Extracts hrefs of every `<link />` whose `rel` attribute satisfies `is_match`.
+fn find_link_hrefs<B, F>(reader: B, is_match: F) -> Result<Vec<String>>
+where
+    B: BufRead,
+    F: Fn(&[u8]) -> bool,
+{
     let mut reader = Reader::from_reader(reader);
     reader.check_end_names(false);
 
@@ -20,7 +38,7 @@ pub fn find_rel_alternates<B: BufRead>(reader: B) -> Result<Vec<String>> {
                     if acc {
                         acc
                     } else if let Ok(attr) = attr {
-                        attr.key == b"rel" && attr.value.as_ref() == b"alternate"
+                        attr.key == b"rel" && is_match(attr.value.as_ref())
                     } else {
                         false
                     }
@@ -54,3 +72,267 @@ pub fn find_rel_alternates<B: BufRead>(reader: B) -> Result<Vec<String>> {
 
     Ok(result)
 }
+
+/// Parses HTML fragment to find `<a href="..." />` and extract hrefs along with their text.
+///
+/// Item contents are rarely well-formed XML, so parsing stops silently at the first error and
+/// returns whatever has been found so far.
+pub fn find_anchors<B: BufRead>(reader: B) -> Vec<(String, String)> {
+    let mut reader = Reader::from_reader(reader);
+    reader.check_end_names(false);
+
+    let mut buf = Vec::new();
+    let mut result = Vec::new();
+    let mut current: Option<(String, String)> = None;
+
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) if e.name() == b"a" => {
+                current = e
+                    .attributes()
+                    .filter_map(|attr| attr.ok())
+                    .find(|attr| attr.key == b"href")
+                    .map(|attr| {
+                        let href = attr
+                            .unescaped_value()
+                            .map(|value| String::from_utf8_lossy(&value).into_owned())
+                            .unwrap_or_else(|_| String::from_utf8_lossy(&attr.value).into_owned());
+                        (href.trim().to_owned(), String::new())
+                    });
+            }
+            Ok(Event::Text(ref e)) => {
+                if let Some((_, text)) = current.as_mut() {
+                    let value = e.unescaped().unwrap_or_else(|_| e.escaped().into());
+                    text.push_str(&String::from_utf8_lossy(&value));
+                }
+            }
+            Ok(Event::End(ref e)) if e.name() == b"a" => {
+                if let Some((href, text)) = current.take() {
+                    result.push((href, text.trim().to_owned()));
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    result
+}
+
+/// Parses feed to find the publishing interval announced with `<sy:updatePeriod />` and
+/// `<sy:updateFrequency />` from the RSS syndication module. Returns the interval in seconds.
+pub fn find_update_period<B: BufRead>(reader: B) -> Option<u32> {
+    let mut reader = Reader::from_reader(reader);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut current: Option<Vec<u8>> = None;
+    let mut period: Option<u32> = None;
+    let mut frequency: u32 = 1;
+
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => {
+                let name = e.name();
+                // only channel level elements are relevant
+                if name == b"item" || name == b"entry" {
+                    break;
+                }
+                current = Some(name.to_vec());
+            }
+            Ok(Event::Text(ref e)) => {
+                let text = e.unescaped().ok();
+                let text = text.as_ref().map(|x| String::from_utf8_lossy(x));
+                match (current.as_deref(), text) {
+                    (Some(b"sy:updatePeriod"), Some(text)) => {
+                        period = match text.trim() {
+                            "hourly" => Some(60 * 60),
+                            "daily" => Some(24 * 60 * 60),
+                            "weekly" => Some(7 * 24 * 60 * 60),
+                            "monthly" => Some(30 * 24 * 60 * 60),
+                            "yearly" => Some(365 * 24 * 60 * 60),
+                            _ => None,
+                        }
+                    }
+                    (Some(b"sy:updateFrequency"), Some(text)) => {
+                        frequency = text.trim().parse().unwrap_or(1).max(1);
+                    }
+                    _ => (),
+                }
+            }
+            Ok(Event::End(_)) => current = None,
+            Ok(Event::Eof) | Err(_) => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    period.map(|period| period / frequency)
+}
+
+/// Checks whether items of an RSS feed carry a `<guid />`.
+pub fn has_item_guids<B: BufRead>(reader: B) -> bool {
+    let mut reader = Reader::from_reader(reader);
+    reader.check_end_names(false);
+
+    let mut buf = Vec::new();
+    let mut in_item = false;
+
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => match e.name() {
+                b"item" => in_item = true,
+                b"guid" if in_item => return true,
+                _ => (),
+            },
+            Ok(Event::End(ref e)) if e.name() == b"item" => in_item = false,
+            Ok(Event::Eof) | Err(_) => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    false
+}
+
+/// Extracts the text of an HTML fragment, e.g. for indexing it. Tags are replaced by spaces
+/// so words of adjacent elements don't run together.
+pub fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => (),
+        }
+    }
+
+    unescape(&text)
+}
+
+/// Renders HTML fragment as plain text to be read in a terminal: paragraphs and line breaks are
+/// kept, list items get a bullet and link targets follow the link text.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut href = None;
+    let mut preformatted = false;
+    let mut hidden = false;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if !hidden {
+            push_text(&mut text, &rest[..start], preformatted);
+        }
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let tag = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "br" => text.push('\n'),
+            "p" | "div" | "blockquote" | "ul" | "ol" | "table" | "hr" | "h1" | "h2" | "h3"
+            | "h4" | "h5" | "h6" => break_paragraph(&mut text),
+            "pre" => {
+                break_paragraph(&mut text);
+                preformatted = !closing;
+            }
+            "li" if !closing => {
+                break_line(&mut text);
+                text.push_str("* ");
+            }
+            "tr" if !closing => break_line(&mut text),
+            "a" if !closing => href = attribute(tag, "href"),
+            "a" => {
+                if let Some(href) = href.take() {
+                    text.push_str(&format!(" <{}>", href));
+                }
+            }
+            "img" => {
+                if let Some(alt) = attribute(tag, "alt").filter(|alt| !alt.is_empty()) {
+                    text.push_str(&format!("[{}]", alt));
+                }
+            }
+            "script" | "style" => hidden = !closing,
+            _ => (),
+        }
+    }
+    if !hidden {
+        push_text(&mut text, rest, preformatted);
+    }
+
+    text.trim().to_owned()
+}
+
+/// Appends text found between two tags. Outside of `<pre>`, whitespace is collapsed the way
+/// browsers do.
+fn push_text(text: &mut String, raw: &str, preformatted: bool) {
+    let raw = unescape(raw);
+    if preformatted {
+        text.push_str(&raw);
+        return;
+    }
+    for c in raw.chars() {
+        if !c.is_whitespace() {
+            text.push(c);
+        } else if !(text.is_empty() || text.ends_with(' ') || text.ends_with('\n')) {
+            text.push(' ');
+        }
+    }
+}
+
+fn break_line(text: &mut String) {
+    text.truncate(text.trim_end_matches(' ').len());
+    if !(text.is_empty() || text.ends_with('\n')) {
+        text.push('\n');
+    }
+}
+
+fn break_paragraph(text: &mut String) {
+    break_line(text);
+    if !(text.is_empty() || text.ends_with("\n\n")) {
+        text.push('\n');
+    }
+}
+
+/// Extracts the value of attribute `name` from the inside of a tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let pattern = format!("{}=", name);
+    let (start, _) = lower
+        .match_indices(&pattern)
+        .find(|(i, _)| lower[..*i].ends_with(char::is_whitespace))?;
+    let value = &tag[start + pattern.len()..];
+    let value = match value.chars().next()? {
+        quote @ '"' | quote @ '\'' => value[1..].split(quote).next()?,
+        _ => value.split(char::is_whitespace).next()?,
+    };
+    Some(unescape(value))
+}
+
+/// Replaces entity and character references. `&nbsp;` is the only HTML entity which is common
+/// enough to be handled, text containing other unknown entities is kept as is.
+fn unescape(text: &str) -> String {
+    let text = text.replace("&nbsp;", " ");
+    match BytesText::from_escaped(text.as_bytes()).unescaped() {
+        Ok(unescaped) => String::from_utf8_lossy(&unescaped).into_owned(),
+        Err(_) => text,
+    }
+}
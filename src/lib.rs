@@ -3,14 +3,14 @@ use structopt::StructOpt;
 #[macro_use]
 mod error;
 
-mod api;
+pub mod api;
 mod cli;
 mod crawler;
 mod find;
 pub mod model;
 mod opml;
 mod remote;
-mod state;
+pub mod state;
 mod utils;
 
 pub async fn cli() -> anyhow::Result<()> {
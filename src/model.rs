@@ -1,10 +1,12 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
+use md5::{Digest, Md5};
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, Connection, OptionalExtension, Row, NO_PARAMS};
+use rusqlite::{params, Connection, OptionalExtension, Row, TransactionBehavior, NO_PARAMS};
 use serde::Serialize;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::rc::Rc;
+use std::time::Duration;
 
 use crate::error::{Error, Result};
 
@@ -60,37 +62,181 @@ impl<T: Model + Sized> ModelExt<T> for T {
 pub struct Group {
     pub id: u32,
     pub title: String,
+    /// Group this one is nested in, `None` for top-level groups
+    #[serde(skip)]
+    pub parent_id: Option<u32>,
 }
 
+/// Recursive common table expression `subgroup(id)` holding the group `?1` and every group
+/// nested in it, at any depth.
+const SUBGROUPS: &str = r"
+        WITH RECURSIVE `subgroup`(`id`) AS (
+            SELECT ?1
+            UNION
+            SELECT `group`.`id` FROM `group` JOIN `subgroup` ON `group`.`parent_id` = `subgroup`.`id`
+        )";
+
+/// Ids of unread items, fetched by Fever clients on every sync.
+const UNREAD_ITEMS: &str = "SELECT id FROM `item` WHERE `is_read` = 0";
+
+/// Items of the feed `?1`, newest first.
+const FEED_ITEMS: &str = "SELECT * FROM `item` WHERE `feed_id` = ?1 ORDER BY `id` DESC";
+
+/// Recursive common table expression `group_tree(group_id, subgroup_id)` pairing every group
+/// with itself and each group nested in it, at any depth.
+const GROUP_TREE: &str = r"
+        WITH RECURSIVE `group_tree`(`group_id`, `subgroup_id`) AS (
+            SELECT `id`, `id` FROM `group`
+            UNION
+            SELECT `group_tree`.`group_id`, `group`.`id`
+            FROM `group_tree` JOIN `group` ON `group`.`parent_id` = `group_tree`.`subgroup_id`
+        )";
+
 impl Group {
     pub fn new(title: String) -> Self {
-        Self { id: 0, title }
+        Self {
+            id: 0,
+            title,
+            parent_id: None,
+        }
     }
 
-    pub fn create_table(conn: &Connection) -> Result<()> {
-        conn.execute(
-            r#"
-        CREATE TABLE IF NOT EXISTS `group` (
-            id INTEGER PRIMARY KEY,
-            title TEXT UNIQUE ON CONFLICT IGNORE
+    /// Returns every group reachable from a top-level group, titled after its full path such as
+    /// `Parent / Child`. Fever only knows flat groups, so this is how it sees the hierarchy.
+    pub fn all_with_paths(conn: &Connection) -> Result<Vec<Self>> {
+        Ok(conn
+            .prepare(
+                r"
+        WITH RECURSIVE `path`(`id`, `title`, `parent_id`) AS (
+            SELECT `id`, `title`, `parent_id` FROM `group` WHERE `parent_id` IS NULL
+            UNION ALL
+            SELECT `group`.`id`, `path`.`title` || ' / ' || `group`.`title`, `group`.`parent_id`
+            FROM `group` JOIN `path` ON `group`.`parent_id` = `path`.`id`
         )
-        "#,
-            NO_PARAMS,
+        SELECT * FROM `path` ORDER BY `id`",
+            )?
+            .query_map(NO_PARAMS, Self::from_row)?
+            .collect::<Result<_, _>>()?)
+    }
+
+    pub fn children(&self, conn: &Connection) -> Result<Vec<Self>> {
+        Ok(conn
+            .prepare("SELECT * FROM `group` WHERE `parent_id` = ?1 ORDER BY `id`")?
+            .query_map(params![self.id], Self::from_row)?
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Nests this group in `parent`, or makes it a top-level group when `parent` is `None`.
+    /// Fails when `parent` is this group or one of its subgroups.
+    pub fn set_parent(mut self, conn: &Connection, parent: Option<&Group>) -> Result<Self> {
+        if let Some(parent) = parent {
+            let is_subgroup = conn.query_row(
+                &format!(
+                    "{} SELECT EXISTS (SELECT 1 FROM `subgroup` WHERE `id` = ?2)",
+                    SUBGROUPS
+                ),
+                params![self.id, parent.id],
+                |row| row.get::<_, bool>(0),
+            )?;
+            if is_subgroup {
+                return Err(Error::message(format!(
+                    "unable to move group '{}' into its own subgroup '{}'",
+                    self.title, parent.title
+                )));
+            }
+        }
+
+        self.parent_id = parent.map(|parent| parent.id);
+        conn.execute(
+            "UPDATE `group` SET `parent_id` = ?2 WHERE `id` = ?1",
+            params![self.id, self.parent_id],
         )?;
-        Ok(())
+        Ok(self)
+    }
+
+    /// Number of unread items of every group, including the items of its subgroups. Groups
+    /// without unread items are left out.
+    pub fn unread_counts(conn: &Connection) -> Result<HashMap<u32, u32>> {
+        Ok(conn
+            .prepare(&format!(
+                r"{}
+        SELECT `group_tree`.`group_id`, COUNT(DISTINCT `item`.`id`)
+        FROM `group_tree`
+        JOIN `feed_group` ON `feed_group`.`group_id` = `group_tree`.`subgroup_id`
+        JOIN `item` ON `item`.`feed_id` = `feed_group`.`feed_id`
+        WHERE `item`.`is_read` = 0
+        GROUP BY `group_tree`.`group_id`",
+                GROUP_TREE
+            ))?
+            .query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?)
     }
 
     pub fn insert(mut self, conn: &Connection) -> Result<Self> {
         self.id = conn
-            .prepare("INSERT INTO `group` (title) VALUES (?1)")?
-            .insert(params![self.title])? as u32;
+            .prepare("INSERT INTO `group` (title, parent_id) VALUES (?1, ?2)")?
+            .insert(params![self.title, self.parent_id])? as u32;
         Ok(self)
     }
 
-    pub fn get_by_name(conn: &Connection, title: &str) -> Result<Self> {
-        conn.prepare("SELECT * FROM `group` WHERE `title` = ?1")?
-            .query_row(params![title], Self::from_row)
-            .map_err(Into::into)
+    /// Finds a group by its title, or by its path such as `Parent / Child`. Titles are only
+    /// unique among the children of a group, so this fails when several groups share the title.
+    pub fn get_by_name(conn: &Connection, name: &str) -> Result<Self> {
+        if name.contains('/') {
+            let path = name
+                .split('/')
+                .map(|title| title.trim().to_owned())
+                .collect::<Vec<_>>();
+            return Self::get_by_path(conn, &path)?
+                .ok_or_else(|| Error::message(format!("no group at path '{}'", name)));
+        }
+
+        let mut groups = conn
+            .prepare("SELECT * FROM `group` WHERE `title` = ?1")?
+            .query_map(params![name], Self::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        match groups.len() {
+            0 => Err(Error::message(format!("no group named '{}'", name))),
+            1 => Ok(groups.remove(0)),
+            _ => {
+                let paths = Self::all_with_paths(conn)?
+                    .into_iter()
+                    .filter(|group| groups.iter().any(|found| found.id == group.id))
+                    .map(|group| group.title)
+                    .collect::<Vec<_>>();
+                Err(Error::message(format!(
+                    "several groups are named '{}', use one of their paths: {}",
+                    name,
+                    paths.join(", ")
+                )))
+            }
+        }
+    }
+
+    /// Finds the group titled `title` nested in `parent_id`, or at the top level when
+    /// `parent_id` is `None`.
+    pub fn get_child(
+        conn: &Connection,
+        parent_id: Option<u32>,
+        title: &str,
+    ) -> Result<Option<Self>> {
+        Ok(conn
+            .prepare("SELECT * FROM `group` WHERE `parent_id` IS ?1 AND `title` = ?2")?
+            .query_row(params![parent_id, title], Self::from_row)
+            .optional()?)
+    }
+
+    /// Finds the group at the end of `path`, the titles of a top-level group and of the groups
+    /// nested in it down to the one looked for.
+    pub fn get_by_path(conn: &Connection, path: &[String]) -> Result<Option<Self>> {
+        let mut group: Option<Self> = None;
+        for title in path.iter() {
+            group = Self::get_child(conn, group.map(|group| group.id), title)?;
+            if group.is_none() {
+                return Ok(None);
+            }
+        }
+        Ok(group)
     }
 
     pub fn get_feeds(&self, conn: &Connection) -> Result<Vec<Feed>> {
@@ -110,15 +256,77 @@ impl Group {
     pub fn add_feed(&self, conn: &Connection, mut feed: Feed) -> Result<Feed> {
         let feed_group = FeedGroup::new(self.id, feed.id);
         feed_group.insert(&conn)?;
-        conn.execute(
-            "UPDATE `feed` SET `is_spark` = 0 WHERE `id` = ?1",
-            params![feed.id],
-        )?;
+        // `is_spark` is maintained by triggers on `feed_group`
         feed.is_spark = false;
         Ok(feed)
     }
 
-    pub fn read(&self, _before: Option<u32>) {}
+    /// Removes `feed` from this group. It becomes a spark unless it belongs to another group.
+    pub fn remove_feed(&self, conn: &Connection, feed: Feed) -> Result<Feed> {
+        let removed = conn.execute(
+            "DELETE FROM `feed_group` WHERE `group_id` = ?1 AND `feed_id` = ?2",
+            params![self.id, feed.id],
+        )?;
+        if removed == 0 {
+            return Err(Error::message(format!(
+                "feed {} is not in group '{}'",
+                feed.id, self.title
+            )));
+        }
+        Feed::get(conn, feed.id)
+    }
+
+    pub fn rename(mut self, conn: &Connection, title: String) -> Result<Self> {
+        // titles are unique among the children of a group
+        let existing = Self::get_child(conn, self.parent_id, &title)?;
+        if matches!(existing, Some(group) if group.id != self.id) {
+            return Err(Error::message(format!("group '{}' already exists", title)));
+        }
+        conn.execute(
+            "UPDATE `group` SET `title` = ?2 WHERE `id` = ?1",
+            params![self.id, title],
+        )?;
+        self.title = title;
+        Ok(self)
+    }
+
+    /// Marks items of every feed in this group and its subgroups as read. When `before` is
+    /// given, only items created on or before that unix timestamp are affected.
+    pub fn read(&self, conn: &Connection, before: Option<u32>) -> Result<usize> {
+        Ok(conn.execute(
+            &format!(
+                r"
+        UPDATE `item` SET `is_read` = 1, `read_on` = ?3
+        WHERE `is_read` = 0 AND `feed_id` IN ({}
+            SELECT `feed_id` FROM `feed_group` WHERE `group_id` IN `subgroup`
+        ) AND (?2 IS NULL OR `created` <= ?2)",
+                SUBGROUPS
+            ),
+            params![self.id, timestamp_to_datetime(before), Utc::now()],
+        )?)
+    }
+
+    /// Marks items of all feeds as read (Fever's "Kindling" pseudo-group, `id = 0`).
+    pub fn read_kindling(conn: &Connection, before: Option<u32>) -> Result<usize> {
+        Ok(conn.execute(
+            r"
+        UPDATE `item` SET `is_read` = 1, `read_on` = ?2
+        WHERE `is_read` = 0 AND (?1 IS NULL OR `created` <= ?1)",
+            params![timestamp_to_datetime(before), Utc::now()],
+        )?)
+    }
+
+    /// Marks items of all ungrouped feeds as read (Fever's "Sparks" pseudo-group, `id = -1`).
+    pub fn read_sparks(conn: &Connection, before: Option<u32>) -> Result<usize> {
+        Ok(conn.execute(
+            r"
+        UPDATE `item` SET `is_read` = 1, `read_on` = ?2
+        WHERE `is_read` = 0 AND `feed_id` IN (
+            SELECT `id` FROM `feed` WHERE `is_spark` = 1
+        ) AND (?1 IS NULL OR `created` <= ?1)",
+            params![timestamp_to_datetime(before), Utc::now()],
+        )?)
+    }
 }
 
 impl Model for Group {
@@ -128,6 +336,7 @@ impl Model for Group {
         Ok(Self {
             id: row.get(0)?,
             title: row.get(1)?,
+            parent_id: row.get(2)?,
         })
     }
 
@@ -140,6 +349,9 @@ impl Model for Group {
     }
 }
 
+/// Id, `updated` time and content hash of a stored item.
+type ItemVersion = (u32, Option<DateTime<Utc>>, Option<String>);
+
 #[derive(Debug, Serialize)]
 pub struct Feed {
     pub id: u32,
@@ -149,9 +361,41 @@ pub struct Feed {
     pub is_spark: bool,
     #[serde(serialize_with = "crate::utils::serialize_timestamp")]
     pub last_updated_on_time: DateTime<Utc>,
+    pub favicon_id: u32,
+    /// Time of the last favicon lookup
+    #[serde(skip)]
+    pub favicon_checked: Option<DateTime<Utc>>,
+    /// `ETag` header of the last successful fetch
+    #[serde(skip)]
+    pub etag: Option<String>,
+    /// `Last-Modified` header of the last successful fetch
+    #[serde(skip)]
+    pub last_modified: Option<String>,
+    /// HTTP status code of the last fetch
+    #[serde(skip)]
+    pub last_status: Option<u16>,
+    /// Crawl interval set by user (unit: seconds)
+    #[serde(skip)]
+    pub fetch_interval: Option<u32>,
+    /// Update interval announced by the feed itself (unit: seconds)
+    #[serde(skip)]
+    pub update_hint: Option<u32>,
+    #[serde(skip)]
+    pub next_fetch: Option<DateTime<Utc>>,
+    /// Error of the last failed crawl, cleared on success
+    #[serde(skip)]
+    pub last_error: Option<String>,
+    /// Number of consecutive failed crawls
+    #[serde(skip)]
+    pub error_count: u32,
+    #[serde(skip)]
+    pub last_success: Option<DateTime<Utc>>,
 }
 
 impl Feed {
+    /// How long to wait before looking up a favicon again after failing to find one.
+    const FAVICON_RETRY_DAYS: i64 = 7;
+
     pub fn new(title: String, url: String, site_url: String) -> Self {
         Feed {
             id: 0,
@@ -160,26 +404,20 @@ impl Feed {
             site_url,
             is_spark: true,
             last_updated_on_time: Utc::now(),
+            favicon_id: 0,
+            favicon_checked: None,
+            etag: None,
+            last_modified: None,
+            last_status: None,
+            fetch_interval: None,
+            update_hint: None,
+            next_fetch: None,
+            last_error: None,
+            error_count: 0,
+            last_success: None,
         }
     }
 
-    pub fn create_table(conn: &Connection) -> Result<()> {
-        conn.execute(
-            r#"
-        CREATE TABLE IF NOT EXISTS `feed` (
-            id INTEGER PRIMARY KEY,
-            title TEXT,
-            url TEXT,
-            site_url TEXT,
-            is_spark BOOLEAN,
-            last_updated DATETIME
-        )
-        "#,
-            NO_PARAMS,
-        )?;
-        Ok(())
-    }
-
     pub fn get_by_url(conn: &Connection, url: &str) -> Result<Option<Self>> {
         Ok(conn
             .query_row(
@@ -192,13 +430,13 @@ impl Feed {
 
     pub fn insert(mut self, conn: &Connection) -> Result<Self> {
         self.id = conn
-            .prepare("INSERT INTO `feed` (title, url, site_url, is_spark, last_updated) VALUES (?1, ?2, ?3, ?4, ?5)")?
-            .insert(params![self.title, self.url, self.site_url, self.is_spark, self.last_updated_on_time])? as u32;
+            .prepare("INSERT INTO `feed` (title, url, site_url, is_spark, last_updated, favicon_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?
+            .insert(params![self.title, self.url, self.site_url, self.is_spark, self.last_updated_on_time, self.favicon_id])? as u32;
         Ok(self)
     }
 
     pub fn items(&self, conn: &Connection, limit: Option<u32>) -> Result<Vec<Item>> {
-        let mut stmt = "SELECT * FROM `item` WHERE `feed_id` = ?1 ORDER BY `id` DESC".to_owned();
+        let mut stmt = FEED_ITEMS.to_owned();
         if let Some(limit) = limit {
             stmt.push_str(&format!(" LIMIT {}", limit));
         }
@@ -208,71 +446,396 @@ impl Feed {
             .collect::<Result<Vec<_>, _>>()?)
     }
 
+    /// Maps guids of stored items to their id, `updated` time and content hash.
+    fn item_versions(&self, conn: &Connection) -> Result<HashMap<String, ItemVersion>> {
+        Ok(conn
+            .prepare(
+                "SELECT `guid`, `id`, `updated`, `content_hash` FROM `item` WHERE `feed_id` = ?1 AND `guid` IS NOT NULL",
+            )?
+            .query_map(params![self.id], |row| {
+                Ok((row.get(0)?, (row.get(1)?, row.get(2)?, row.get(3)?)))
+            })?
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Guids of items purged from this feed.
+    fn purged_guids(&self, conn: &Connection) -> Result<HashSet<String>> {
+        Ok(conn
+            .prepare("SELECT `guid` FROM `item_tombstone` WHERE `feed_id` = ?1")?
+            .query_map(params![self.id], |row| row.get(0))?
+            .collect::<Result<_, _>>()?)
+    }
+
+    fn update_status(&mut self, conn: &Connection, status: u16) -> Result<()> {
+        conn.execute(
+            "UPDATE `feed` SET `last_status` = ?1 WHERE id = ?2",
+            params![status, self.id],
+        )?;
+        self.last_status = Some(status);
+        Ok(())
+    }
+
     pub async fn crawl(mut self, state: crate::state::State) -> Result<Self> {
-        let exist_urls = {
+        let mut request = surf::get(&self.url);
+        if let Some(etag) = self.etag.as_ref() {
+            request = request.set_header("If-None-Match", etag);
+        }
+        if let Some(last_modified) = self.last_modified.as_ref() {
+            request = request.set_header("If-Modified-Since", last_modified);
+        }
+        let mut response = request.await?;
+
+        let status = response.status().as_u16();
+        if status == 304 {
             let conn = state.db.get()?;
-            self.items(&conn, None)?
-                .into_iter()
-                .map(|item| item.url)
-                .collect::<HashSet<String>>()
-        };
-        let content = surf::get(&self.url).await?.body_bytes().await?;
+            self.update_status(&conn, status)?;
+            return Ok(self);
+        } else if !response.status().is_success() {
+            let conn = state.db.get()?;
+            self.update_status(&conn, status)?;
+            return Err(Error::message(format!(
+                "unexpected status {} when fetching {}",
+                status, self.url
+            )));
+        }
+
+        let etag = response.header("ETag").map(ToOwned::to_owned);
+        let last_modified = response.header("Last-Modified").map(ToOwned::to_owned);
+        let content = response.body_bytes().await?;
         let feed = feed_rs::parser::parse(&content[..])?;
+        // hints are up to the remote server, and none is followed beyond the longest interval
+        let update_hint = feed
+            .ttl
+            .map(|minutes| minutes.saturating_mul(60))
+            .or_else(|| crate::find::find_update_period(&content[..]))
+            .map(|hint| hint.min(Self::MAX_CRAWL_INTERVAL));
+
+        // feed-rs makes up ids for RSS entries without `<guid>`, which are not stable
+        let has_guids = match feed.feed_type {
+            feed_rs::model::FeedType::Atom | feed_rs::model::FeedType::JSON => true,
+            _ => crate::find::has_item_guids(&content[..]),
+        };
+
+        let (exist, purged) = {
+            let conn = state.db.get()?;
+            (self.item_versions(&conn)?, self.purged_guids(&conn)?)
+        };
 
+        let mut seen = HashSet::new();
         let mut items = Vec::new();
+        let mut revised = Vec::new();
+        let mut adopted = Vec::new();
+        let mut relisted = Vec::new();
         for item in feed.entries.into_iter().rev() {
-            if let Some(link) = item.links.first() {
-                if exist_urls.contains(&link.href) {
-                    continue;
-                }
-
-                let created = if let Some(published) = item.published {
-                    published
-                } else {
-                    continue;
-                };
+            let (created, date_source) = match (item.published, item.updated) {
+                (Some(published), _) => (published, DateSource::Published),
+                (None, Some(updated)) => (updated, DateSource::Updated),
+                (None, None) => (Utc::now(), DateSource::FirstSeen),
+            };
+
+            let author = item
+                .authors
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>()
+                .join(",");
+            let url = item
+                .links
+                .first()
+                .map(|link| link.href.clone())
+                .unwrap_or_default();
+            let title = item.title.map(|t| t.content).unwrap_or_default();
+            let html = item
+                .content
+                .and_then(|c| c.body)
+                .or(item.summary.map(|c| c.content))
+                .unwrap_or_default();
+            let content_hash = Item::content_hash(&title, &html);
+            let guid = if has_guids && !item.id.is_empty() {
+                item.id
+            } else {
+                content_hash.clone()
+            };
+
+            // items stored before guids were recorded have their url as guid
+            let is_legacy = !exist.contains_key(&guid) && !url.is_empty();
+            if !seen.insert(guid.clone()) {
+                continue;
+            }
+            if purged.contains(&guid) {
+                relisted.push(guid);
+                continue;
+            }
+            if is_legacy && purged.contains(&url) {
+                relisted.push(url);
+                continue;
+            }
 
-                let author = item
-                    .authors
-                    .iter()
-                    .map(|a| a.name.as_str())
-                    .collect::<Vec<_>>()
-                    .join(",");
-                let url = link.href.clone();
-
-                items.push(Item {
-                    id: 0,
-                    feed_id: self.id,
-                    title: item.title.map(|t| t.content).unwrap_or_default(),
-                    author,
-                    html: item
-                        .content
-                        .and_then(|c| c.body)
-                        .or(item.summary.map(|c| c.content))
-                        .unwrap_or_default(),
-                    url,
-                    is_saved: false,
-                    is_read: false,
-                    created_on_time: created,
-                });
+            let key = if is_legacy { &url } else { &guid };
+            let id = match exist.get(key) {
+                Some((id, updated, hash)) => {
+                    if is_legacy {
+                        adopted.push((*id, guid.clone()));
+                    }
+                    let is_newer = match (item.updated, updated) {
+                        (Some(new), Some(old)) => new > *old,
+                        _ => false,
+                    };
+                    let is_changed = matches!(hash, Some(hash) if *hash != content_hash);
+                    if !is_newer && !is_changed {
+                        continue;
+                    }
+                    *id
+                }
+                None => 0,
+            };
+
+            let entry = Item {
+                id,
+                feed_id: self.id,
+                title,
+                author,
+                html,
+                url,
+                is_saved: false,
+                is_read: false,
+                created_on_time: created,
+                read_on_time: None,
+                guid,
+                date_source,
+                updated_on_time: item.updated,
+                content_hash,
+            };
+            if entry.id == 0 {
+                items.push(entry);
+            } else {
+                revised.push(entry);
             }
         }
 
         let now = Utc::now();
         {
-            let conn = state.db.get()?;
-            Item::insert_multi(&conn, items)?;
-            conn.execute(
-                "UPDATE `feed` SET `last_updated` = ?1 WHERE id = ?2",
-                params![now, self.id],
+            // a feed is stored either completely or not at all. The write lock is taken up
+            // front, as a deferred transaction may fail as busy rather than wait for it
+            let mut conn = state.db.get()?;
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let inserted = Item::insert_multi(&tx, items)?;
+            Link::insert_from_items(&tx, &inserted.items)?;
+            log::debug!(
+                "feed {}: {} new items, {} skipped, {} updated",
+                self.id,
+                inserted.items.len(),
+                inserted.skipped,
+                revised.len()
+            );
+            for item in revised.into_iter() {
+                item.revise(&tx)?;
+            }
+            for (id, guid) in adopted.into_iter() {
+                tx.execute(
+                    "UPDATE `item` SET `guid` = ?1 WHERE `id` = ?2",
+                    params![guid, id],
+                )?;
+            }
+            // purged entries the feed still lists must not be pruned
+            let relisted = Rc::new(
+                relisted
+                    .into_iter()
+                    .map(rusqlite::types::Value::from)
+                    .collect::<Vec<_>>(),
+            );
+            tx.execute(
+                "UPDATE `item_tombstone` SET `purged` = ?3 WHERE `feed_id` = ?1 AND `guid` IN rarray(?2)",
+                params![self.id, relisted, now],
+            )?;
+            tx.execute(
+                r"
+            UPDATE `feed`
+            SET `last_updated` = ?1, `etag` = ?2, `last_modified` = ?3, `last_status` = ?4,
+                `update_hint` = ?5
+            WHERE id = ?6",
+                params![now, etag, last_modified, status, update_hint, self.id],
             )?;
+            tx.commit()?;
         }
         self.last_updated_on_time = now;
+        self.etag = etag;
+        self.last_modified = last_modified;
+        self.last_status = Some(status);
+        self.update_hint = update_hint;
+
+        Ok(self)
+    }
+
+    /// Feeds are never crawled less often than this.
+    pub const MAX_CRAWL_INTERVAL: u32 = 24 * 60 * 60;
+
+    /// Decides how long to wait between two crawls of this feed (unit: seconds).
+    ///
+    /// A user-defined interval always wins. Otherwise the average gap between the latest items
+    /// is used, but never below the interval announced by the feed or `default_interval`.
+    /// Failing feeds back off exponentially, up to `MAX_CRAWL_INTERVAL`.
+    pub fn crawl_interval(&self, conn: &Connection, default_interval: u32) -> Result<u32> {
+        let interval = match self.fetch_interval {
+            Some(interval) => interval,
+            None => self.observed_interval(conn, default_interval)?,
+        };
+
+        if self.error_count == 0 {
+            return Ok(interval);
+        }
+        let backoff = 2u32.saturating_pow(self.error_count.min(16));
+        Ok(interval
+            .saturating_mul(backoff)
+            .min(Self::MAX_CRAWL_INTERVAL.max(interval)))
+    }
+
+    fn observed_interval(&self, conn: &Connection, default_interval: u32) -> Result<u32> {
+        let created = conn
+            .prepare("SELECT `created` FROM `item` WHERE `feed_id` = ?1 ORDER BY `created` DESC LIMIT 10")?
+            .query_map(params![self.id], |row| row.get::<_, DateTime<Utc>>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let observed = match (created.first(), created.last()) {
+            (Some(newest), Some(oldest)) if created.len() > 1 => {
+                let span = newest.signed_duration_since(*oldest).num_seconds().max(0);
+                Some((span / (created.len() as i64 - 1)) as u32)
+            }
+            _ => None,
+        };
+
+        let interval = observed
+            .unwrap_or(default_interval)
+            .max(self.update_hint.unwrap_or(0))
+            .max(default_interval)
+            .min(Self::MAX_CRAWL_INTERVAL.max(default_interval));
+        Ok(interval)
+    }
+
+    /// Records a successful crawl, clearing previous errors.
+    pub fn record_success(mut self, conn: &Connection) -> Result<Self> {
+        let now = Utc::now();
+        conn.execute(
+            "UPDATE `feed` SET `last_error` = NULL, `error_count` = 0, `last_success` = ?1 WHERE id = ?2",
+            params![now, self.id],
+        )?;
+        self.last_error = None;
+        self.error_count = 0;
+        self.last_success = Some(now);
+        Ok(self)
+    }
+
+    /// Records a failed crawl, so the feed is backed off next time it gets scheduled.
+    pub fn record_failure(mut self, conn: &Connection, error: &str) -> Result<Self> {
+        conn.execute(
+            "UPDATE `feed` SET `last_error` = ?1, `error_count` = `error_count` + 1 WHERE id = ?2",
+            params![error, self.id],
+        )?;
+        self.last_error = Some(error.to_owned());
+        self.error_count += 1;
+        Ok(self)
+    }
+
+    /// Sets the time this feed should be crawled next based on `crawl_interval`.
+    pub fn schedule(mut self, conn: &Connection, default_interval: u32) -> Result<Self> {
+        let interval = self.crawl_interval(conn, default_interval)?;
+        let next_fetch = Utc::now() + chrono::Duration::seconds(interval as i64);
+        conn.execute(
+            "UPDATE `feed` SET `next_fetch` = ?1 WHERE id = ?2",
+            params![next_fetch, self.id],
+        )?;
+        self.next_fetch = Some(next_fetch);
+        Ok(self)
+    }
+
+    /// Stores changes of `title`, `url` and `site_url`. A new `url` is a different resource, so
+    /// its caching headers are dropped and it gets crawled right away.
+    pub fn update(self, conn: &Connection) -> Result<Self> {
+        conn.execute(
+            r"
+        UPDATE `feed` SET
+            `title` = ?2,
+            `url` = ?3,
+            `site_url` = ?4,
+            `etag` = CASE WHEN `url` = ?3 THEN `etag` END,
+            `last_modified` = CASE WHEN `url` = ?3 THEN `last_modified` END,
+            `next_fetch` = CASE WHEN `url` = ?3 THEN `next_fetch` END
+        WHERE `id` = ?1",
+            params![self.id, self.title, self.url, self.site_url],
+        )?;
+        Self::get(conn, self.id)
+    }
+
+    pub fn set_fetch_interval(mut self, conn: &Connection, interval: Option<u32>) -> Result<Self> {
+        conn.execute(
+            "UPDATE `feed` SET `fetch_interval` = ?1, `next_fetch` = NULL WHERE id = ?2",
+            params![interval, self.id],
+        )?;
+        self.fetch_interval = interval;
+        self.next_fetch = None;
+        Ok(self)
+    }
+
+    /// Selects feeds that have never been crawled or whose next crawl is due at `now`.
+    pub fn due(conn: &Connection, now: DateTime<Utc>) -> Result<Vec<Self>> {
+        Ok(conn
+            .prepare("SELECT * FROM `feed` WHERE `next_fetch` IS NULL OR `next_fetch` <= ?1")?
+            .query_map(params![now], Self::from_row)?
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Returns the earliest scheduled crawl among all feeds.
+    pub fn next_due(conn: &Connection) -> Result<Option<DateTime<Utc>>> {
+        Ok(
+            conn.query_row("SELECT MIN(`next_fetch`) FROM `feed`", NO_PARAMS, |row| {
+                row.get(0)
+            })?,
+        )
+    }
+
+    /// Whether the favicon of this feed should be looked up: it has none yet, and the last
+    /// lookup, if any, failed long enough ago.
+    pub fn needs_favicon(&self, now: DateTime<Utc>) -> bool {
+        let retry = chrono::Duration::days(Self::FAVICON_RETRY_DAYS);
+        self.favicon_id == 0
+            && !matches!(self.favicon_checked, Some(checked) if now - checked < retry)
+    }
+
+    /// Fetches the favicon of `site_url` and links it to this feed. The lookup is recorded
+    /// either way, so a site without one isn't searched on every crawl.
+    pub async fn update_favicon(mut self, state: crate::state::State) -> Result<Self> {
+        let fetched = crate::remote::fetch_favicon(&self.site_url).await;
+
+        let conn = state.db.get()?;
+        self.favicon_checked = Some(Utc::now());
+        conn.execute(
+            "UPDATE `feed` SET `favicon_checked` = ?1 WHERE id = ?2",
+            params![self.favicon_checked, self.id],
+        )?;
+        let data = fetched?;
+
+        let favicon = match Favicon::get_by_data(&conn, &data)? {
+            Some(favicon) => favicon,
+            None => Favicon::new(data).insert(&conn)?,
+        };
+        conn.execute(
+            "UPDATE `feed` SET `favicon_id` = ?1 WHERE id = ?2",
+            params![favicon.id, self.id],
+        )?;
+        self.favicon_id = favicon.id;
 
         Ok(self)
     }
 
-    pub fn read(&self, _before: Option<u32>) {}
+    /// Marks items of this feed as read. When `before` is given, only items created on or
+    /// before that unix timestamp are affected.
+    pub fn read(&self, conn: &Connection, before: Option<u32>) -> Result<usize> {
+        Ok(conn.execute(
+            r"
+        UPDATE `item` SET `is_read` = 1, `read_on` = ?3
+        WHERE `is_read` = 0 AND `feed_id` = ?1 AND (?2 IS NULL OR `created` <= ?2)",
+            params![self.id, timestamp_to_datetime(before), Utc::now()],
+        )?)
+    }
 }
 
 impl Model for Feed {
@@ -286,6 +849,17 @@ impl Model for Feed {
             site_url: row.get(3)?,
             is_spark: row.get(4)?,
             last_updated_on_time: row.get(5)?,
+            favicon_id: row.get(6)?,
+            favicon_checked: row.get(7)?,
+            etag: row.get(8)?,
+            last_modified: row.get(9)?,
+            last_status: row.get(10)?,
+            fetch_interval: row.get(11)?,
+            update_hint: row.get(12)?,
+            next_fetch: row.get(13)?,
+            last_error: row.get(14)?,
+            error_count: row.get(15)?,
+            last_success: row.get(16)?,
         })
     }
 
@@ -326,21 +900,6 @@ impl FeedGroup {
         ser.serialize_str(&crate::utils::comma_join_vec(ids))
     }
 
-    pub fn create_table(conn: &Connection) -> Result<()> {
-        conn.execute(
-            r#"
-        CREATE TABLE IF NOT EXISTS `feed_group` (
-            id INTEGER PRIMARY KEY,
-            group_id INTEGER,
-            feed_id INTEGER,
-            UNIQUE(group_id, feed_id) ON CONFLICT IGNORE
-        )
-        "#,
-            NO_PARAMS,
-        )?;
-        Ok(())
-    }
-
     fn fold_group(mut indices: Vec<(u32, u32)>) -> Result<Vec<Self>> {
         indices.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
         Ok(indices
@@ -369,11 +928,23 @@ impl FeedGroup {
         Self::fold_group(indices)
     }
 
-    pub fn delete_by_feed(conn: &Connection, feed_id: u32) -> Result<usize> {
-        Ok(conn.execute(
-            "DELETE FROM `feed_group` WHERE `feed_id` = ?1",
-            params![feed_id],
-        )?)
+    /// Like `all`, except that the feeds of subgroups are listed under every group they are
+    /// nested in as well, so that Fever clients roll up unread counts.
+    pub fn rolled_up(conn: &Connection) -> Result<Vec<Self>> {
+        let indices = conn
+            .prepare(&format!(
+                r"{}
+        SELECT DISTINCT `group_tree`.`group_id`, `feed_group`.`feed_id`
+        FROM `group_tree`
+        JOIN `feed_group` ON `feed_group`.`group_id` = `group_tree`.`subgroup_id`",
+                GROUP_TREE
+            ))?
+            .query_map(NO_PARAMS, |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, u32>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Self::fold_group(indices)
     }
 
     pub fn get_by_group(conn: &Connection, group_id: u32) -> Result<Self> {
@@ -409,35 +980,46 @@ impl FeedGroup {
                 .map(rusqlite::types::Value::from)
                 .collect::<Vec<_>>(),
         );
-        conn.prepare("UPDATE `feed` SET `is_spark` = 1 WHERE `id` IN rarray(?) ")?
-            .execute(&[&rarray])?;
-        conn.execute(
-            "DELETE FROM `feed_group` WHERE `group_id` = ?1",
-            params![self.group_id],
-        )?;
+        conn.prepare("DELETE FROM `feed_group` WHERE `group_id` = ?1 AND `feed_id` IN rarray(?2)")?
+            .execute(params![self.group_id, rarray])?;
         self.feed_ids.clear();
         Ok(self)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Favicon {
-    id: u32,
-    data: String,
+    pub id: u32,
+    /// Icon encoded as `data:` URI
+    #[serde(serialize_with = "Favicon::serialize_data")]
+    pub data: String,
 }
 
 impl Favicon {
-    pub fn create_table(conn: &Connection) -> Result<()> {
-        conn.execute(
-            r#"
-        CREATE TABLE IF NOT EXISTS `favicon` (
-            id INTEGER PRIMARY KEY,
-            data BLOB
-        )
-        "#,
-            NO_PARAMS,
-        )?;
-        Ok(())
+    pub fn new(data: String) -> Self {
+        Self { id: 0, data }
+    }
+
+    /// Fever expects `<mime>;base64,<data>` without the `data:` scheme.
+    pub fn serialize_data<S: serde::Serializer>(data: &str, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_str(data.trim_start_matches("data:"))
+    }
+
+    pub fn insert(mut self, conn: &Connection) -> Result<Self> {
+        self.id = conn
+            .prepare("INSERT INTO `favicon` (data) VALUES (?1)")?
+            .insert(params![self.data])? as u32;
+        Ok(self)
+    }
+
+    pub fn get_by_data(conn: &Connection, data: &str) -> Result<Option<Self>> {
+        Ok(conn
+            .query_row(
+                "SELECT * FROM `favicon` WHERE `data` = ?1",
+                params![data],
+                Self::from_row,
+            )
+            .optional()?)
     }
 }
 
@@ -460,6 +1042,33 @@ impl Model for Favicon {
     }
 }
 
+/// Which date of a feed entry an item's creation time comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateSource {
+    Published,
+    Updated,
+    /// The entry has no date, so the time it was first crawled is used
+    FirstSeen,
+}
+
+impl DateSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DateSource::Published => "published",
+            DateSource::Updated => "updated",
+            DateSource::FirstSeen => "first_seen",
+        }
+    }
+
+    fn from_str(source: &str) -> Self {
+        match source {
+            "updated" => DateSource::Updated,
+            "first_seen" => DateSource::FirstSeen,
+            _ => DateSource::Published,
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct Item {
     pub id: u32,
@@ -472,38 +1081,88 @@ pub struct Item {
     pub is_read: bool,
     #[serde(serialize_with = "crate::utils::serialize_timestamp")]
     pub created_on_time: DateTime<Utc>,
+    /// When the item was last marked as read
+    #[serde(skip)]
+    pub read_on_time: Option<DateTime<Utc>>,
+    /// Entry id (RSS `<guid>`, Atom `<id>`) or a hash of the content when the feed has none
+    #[serde(skip)]
+    pub guid: String,
+    /// Where `created_on_time` was taken from
+    #[serde(skip)]
+    pub date_source: DateSource,
+    /// `updated` time of the entry as last seen
+    #[serde(skip)]
+    pub updated_on_time: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub content_hash: String,
+}
+
+/// Narrows down the items returned by `Item::list` and `Item::search`.
+#[derive(Debug, Default)]
+pub struct ItemFilter {
+    pub feed_id: Option<u32>,
+    pub group_id: Option<u32>,
+    /// Only returns unread items
+    pub unread: bool,
+    /// Only returns saved items
+    pub saved: bool,
+    /// Only returns items created at or after this time
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl ItemFilter {
+    /// `WHERE` condition on `item` taking the filter as parameters `?1` to `?5`. Filtering by
+    /// group includes the feeds of its subgroups.
+    const CONDITION: &'static str = r"
+            (?1 IS NULL OR `item`.`feed_id` = ?1)
+            AND (?2 IS NULL OR `item`.`feed_id` IN (
+                WITH RECURSIVE `subgroup`(`id`) AS (
+                    SELECT ?2
+                    UNION
+                    SELECT `group`.`id` FROM `group`
+                    JOIN `subgroup` ON `group`.`parent_id` = `subgroup`.`id`
+                )
+                SELECT `feed_id` FROM `feed_group` WHERE `group_id` IN `subgroup`
+            ))
+            AND NOT (?3 AND `item`.`is_read` = 1)
+            AND NOT (?4 AND `item`.`is_saved` = 0)
+            AND (?5 IS NULL OR `item`.`created` >= ?5)";
+}
+
+/// Outcome of `Item::insert_multi`.
+#[derive(Debug)]
+pub struct InsertedItems {
+    /// Newly inserted items, with their ids assigned
+    pub items: Vec<Item>,
+    /// Number of items whose `guid` was already taken
+    pub skipped: usize,
 }
 
 impl Item {
-    pub fn create_table(conn: &Connection) -> Result<()> {
-        conn.execute(
-            r#"
-        CREATE TABLE IF NOT EXISTS `item` (
-            id INTEGER PRIMARY KEY,
-            feed_id INTEGER,
-            title TEXT,
-            author TEXT,
-            html BLOB,
-            url TEXT,
-            is_saved BOOLEAN,
-            is_read BOOLEAN,
-            created DATETIME
-        )
-        "#,
-            NO_PARAMS,
-        )?;
-        Ok(())
+    /// Identifies an entry by its title and content, for feeds lacking entry ids.
+    pub fn content_hash(title: &str, html: &str) -> String {
+        let mut hasher = Md5::new();
+        hasher.update(title);
+        hasher.update("\n");
+        hasher.update(html);
+        format!("md5:{:x}", hasher.finalize())
     }
 
-    pub fn insert_multi(conn: &Connection, items: Vec<Item>) -> Result<()> {
+    /// Inserts items and returns them with their ids assigned. Items whose `guid` already
+    /// exists in the same feed are skipped. Callers inserting many items should do so within
+    /// a transaction.
+    pub fn insert_multi(conn: &Connection, items: Vec<Item>) -> Result<InsertedItems> {
         let mut stmt = conn.prepare(
             r"
-        INSERT INTO `item` (feed_id, title, author, html, url, is_saved, is_read, created)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        INSERT OR IGNORE INTO `item` (feed_id, title, author, html, url, is_saved, is_read, created, guid,
+            date_source, updated, content_hash)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
         )?;
 
-        for item in items.into_iter() {
-            stmt.execute(params![
+        let mut inserted = Vec::with_capacity(items.len());
+        let mut skipped = 0;
+        for mut item in items.into_iter() {
+            let changes = stmt.execute(params![
                 item.feed_id,
                 item.title,
                 item.author,
@@ -512,35 +1171,183 @@ impl Item {
                 item.is_saved,
                 item.is_read,
                 item.created_on_time,
+                item.guid,
+                item.date_source.as_str(),
+                item.updated_on_time,
+                item.content_hash,
             ])?;
+            if changes == 0 {
+                skipped += 1;
+                continue;
+            }
+            item.id = conn.last_insert_rowid() as u32;
+            item.index(conn)?;
+            inserted.push(item);
         }
 
         stmt.finalize()?;
-        Ok(())
+        Ok(InsertedItems {
+            items: inserted,
+            skipped,
+        })
     }
 
-    pub fn select(conn: &Connection, offset: Option<u32>, desc: bool) -> Result<Vec<Self>> {
-        let mut stmt = "SELECT * FROM `item` ORDER BY `id` ".to_owned();
-        if desc {
-            stmt.push_str("DESC");
+    /// Selects up to 50 items. With `since_id`, items with a greater id are returned in
+    /// ascending order; with `max_id`, items with a smaller id are returned in descending order.
+    pub fn select(
+        conn: &Connection,
+        since_id: Option<u32>,
+        max_id: Option<u32>,
+    ) -> Result<Vec<Self>> {
+        let mut stmt = r"
+        SELECT * FROM `item`
+        WHERE (?1 IS NULL OR `id` > ?1) AND (?2 IS NULL OR `id` < ?2)
+        ORDER BY `id`"
+            .to_owned();
+        if max_id.is_some() {
+            stmt.push_str(" DESC");
         }
         stmt.push_str(" LIMIT 50");
-        if let Some(offset) = offset {
-            stmt.push_str(&format!(" OFFSET {}", offset));
-        }
         Ok(conn
             .prepare(&stmt)?
-            .query_map(NO_PARAMS, Self::from_row)?
+            .query_map(params![since_id, max_id], Self::from_row)?
             .collect::<Result<_, _>>()?)
     }
 
-    pub fn delete_by_feed(conn: &Connection, feed_id: u32) -> Result<usize> {
-        Ok(conn.execute("DELETE FROM `item` WHERE `feed_id` = ?1", params![feed_id])?)
+    /// Selects items by their ids, at most 50 of them.
+    pub fn with_ids(conn: &Connection, ids: &[u32]) -> Result<Vec<Self>> {
+        let rarray = Rc::new(
+            ids.iter()
+                .take(50)
+                .map(|&s| s as i64)
+                .map(rusqlite::types::Value::from)
+                .collect::<Vec<_>>(),
+        );
+        Ok(conn
+            .prepare("SELECT * FROM `item` WHERE `id` IN rarray(?) ORDER BY `id`")?
+            .query_map(&[&rarray], Self::from_row)?
+            .collect::<Result<_, _>>()?)
     }
 
-    pub fn unread(conn: &Connection) -> Result<Vec<u32>> {
+    /// Replaces the stored content of this item, keeping the previous version as a revision.
+    pub fn revise(self, conn: &Connection) -> Result<Self> {
+        conn.execute(
+            r"
+        INSERT INTO `item_revision` (item_id, title, author, html, url, updated, replaced)
+        SELECT id, title, author, html, url, updated, ?2 FROM `item` WHERE id = ?1",
+            params![self.id, Utc::now()],
+        )?;
+        conn.execute(
+            r"
+        UPDATE `item`
+        SET `title` = ?1, `author` = ?2, `html` = ?3, `url` = ?4, `updated` = ?5,
+            `content_hash` = ?6
+        WHERE id = ?7",
+            params![
+                self.title,
+                self.author,
+                self.html,
+                self.url,
+                self.updated_on_time,
+                self.content_hash,
+                self.id
+            ],
+        )?;
+        self.index(conn)?;
+        Ok(self)
+    }
+
+    /// Adds this item to the full-text search index, replacing what was indexed before. Tags
+    /// are stripped here rather than in a trigger, so any SQLite client can write to `item`.
+    fn index(&self, conn: &Connection) -> Result<()> {
+        conn.execute(
+            "DELETE FROM `item_search` WHERE `rowid` = ?1",
+            params![self.id],
+        )?;
+        conn.execute(
+            "INSERT INTO `item_search` (rowid, title, author, content) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                self.id,
+                self.title,
+                self.author,
+                crate::find::strip_tags(&self.html)
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Finds up to `limit` items matching all words of `text`, best matches first. Matches in
+    /// the title weigh more than those in the content.
+    pub fn search(
+        conn: &Connection,
+        text: &str,
+        filter: &ItemFilter,
+        limit: u32,
+    ) -> Result<Vec<Self>> {
+        // every word is quoted, so input can't be mistaken for FTS5 query syntax
+        let query = text
+            .split_whitespace()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let sql = format!(
+            r"
+        SELECT `item`.* FROM `item_search` JOIN `item` ON `item`.`id` = `item_search`.`rowid`
+        WHERE {} AND `item_search` MATCH ?6
+        ORDER BY bm25(`item_search`, 10.0, 1.0, 1.0)
+        LIMIT ?7",
+            ItemFilter::CONDITION
+        );
+        Ok(conn
+            .prepare(&sql)?
+            .query_map(
+                params![
+                    filter.feed_id,
+                    filter.group_id,
+                    filter.unread,
+                    filter.saved,
+                    filter.since,
+                    query,
+                    limit
+                ],
+                Self::from_row,
+            )?
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Returns up to `limit` items matching `filter`, newest first.
+    pub fn list(conn: &Connection, filter: &ItemFilter, limit: u32) -> Result<Vec<Self>> {
+        let sql = format!(
+            r"
+        SELECT * FROM `item`
+        WHERE {}
+        ORDER BY `created` DESC, `id` DESC
+        LIMIT ?6",
+            ItemFilter::CONDITION
+        );
         Ok(conn
-            .prepare("SELECT id FROM `item` WHERE `is_read` = 0")?
+            .prepare(&sql)?
+            .query_map(
+                params![
+                    filter.feed_id,
+                    filter.group_id,
+                    filter.unread,
+                    filter.saved,
+                    filter.since,
+                    limit
+                ],
+                Self::from_row,
+            )?
+            .collect::<Result<_, _>>()?)
+    }
+
+    pub fn unread(conn: &Connection) -> Result<Vec<u32>> {
+        Ok(conn
+            .prepare(UNREAD_ITEMS)?
             .query_map(NO_PARAMS, |row| row.get(0))?
             .collect::<Result<_, _>>()?)
     }
@@ -553,14 +1360,39 @@ impl Item {
     }
 
     pub fn read(mut self, conn: &Connection) -> Result<Self> {
+        let now = Utc::now();
         conn.execute(
-            "UPDATE `item` SET `is_read` = 1 WHERE `id` = ?1",
-            params![self.id],
+            "UPDATE `item` SET `is_read` = 1, `read_on` = ?1 WHERE `id` = ?2",
+            params![now, self.id],
         )?;
         self.is_read = true;
+        self.read_on_time = Some(now);
         Ok(self)
     }
 
+    pub fn mark_unread(mut self, conn: &Connection) -> Result<Self> {
+        conn.execute(
+            "UPDATE `item` SET `is_read` = 0, `read_on` = NULL WHERE `id` = ?1",
+            params![self.id],
+        )?;
+        self.is_read = false;
+        self.read_on_time = None;
+        Ok(self)
+    }
+
+    /// Marks items read by the most recent read operation as unread again. Calling it
+    /// repeatedly walks back through earlier operations.
+    pub fn unread_recently_read(conn: &Connection) -> Result<usize> {
+        Ok(conn.execute(
+            r"
+        UPDATE `item` SET `is_read` = 0, `read_on` = NULL
+        WHERE `is_read` = 1 AND `read_on` = (
+            SELECT MAX(`read_on`) FROM `item` WHERE `is_read` = 1
+        )",
+            NO_PARAMS,
+        )?)
+    }
+
     pub fn save(mut self, conn: &Connection) -> Result<Self> {
         conn.execute(
             "UPDATE `item` SET `is_saved` = 1 WHERE `id` = ?1",
@@ -594,6 +1426,14 @@ impl Model for Item {
             is_saved: row.get(6)?,
             is_read: row.get(7)?,
             created_on_time: row.get(8)?,
+            read_on_time: row.get(9)?,
+            guid: row.get::<_, Option<String>>(10)?.unwrap_or_default(),
+            date_source: row
+                .get::<_, Option<String>>(11)?
+                .map(|source| DateSource::from_str(&source))
+                .unwrap_or(DateSource::Published),
+            updated_on_time: row.get(12)?,
+            content_hash: row.get::<_, Option<String>>(13)?.unwrap_or_default(),
         })
     }
 
@@ -606,23 +1446,807 @@ impl Model for Item {
     }
 }
 
-pub fn get_pool(path: &Path) -> Result<r2d2::Pool<SqliteConnectionManager>> {
-    let manager = SqliteConnectionManager::file(path).with_init(|c| {
-        rusqlite::vtab::array::load_module(&c)?;
+/// Decides which items are deleted to keep the database from growing forever.
+#[derive(Debug, Clone, Default)]
+pub struct Retention {
+    /// Items created longer ago than this are purged
+    pub max_age: Option<chrono::Duration>,
+    /// Only this many of the newest items of each feed are kept
+    pub max_items_per_feed: Option<u32>,
+    /// Saved items are never purged
+    pub keep_saved: bool,
+}
+
+impl Retention {
+    /// How long purged items are remembered once their feed stopped listing them (unit: days).
+    const TOMBSTONE_DAYS: i64 = 30;
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_age.is_some() || self.max_items_per_feed.is_some()
+    }
+
+    /// Returns ids of items that are due to be purged at `now`.
+    pub fn expired_items(&self, conn: &Connection, now: DateTime<Utc>) -> Result<Vec<u32>> {
+        if !self.is_enabled() {
+            return Ok(Vec::new());
+        }
+
+        Ok(conn
+            .prepare(
+                r"
+        SELECT `id` FROM (
+            SELECT `id`, `created`, `is_saved`, ROW_NUMBER() OVER (
+                PARTITION BY `feed_id` ORDER BY `created` DESC, `id` DESC
+            ) AS `rank`
+            FROM `item`
+        )
+        WHERE ((?1 IS NOT NULL AND `created` < ?1) OR (?2 IS NOT NULL AND `rank` > ?2))
+            AND NOT (?3 AND `is_saved` = 1)
+        ORDER BY `id`",
+            )?
+            .query_map(
+                params![
+                    self.max_age.map(|age| now - age),
+                    self.max_items_per_feed,
+                    self.keep_saved
+                ],
+                |row| row.get(0),
+            )?
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Deletes expired items and returns how many were deleted. Their guids are remembered, so
+    /// they are not stored again when still present in the feed. Items stored before guids
+    /// were recorded are remembered by their url, the way crawling matches them.
+    ///
+    /// Crawls push the purge time of entries still listed back, so guids are forgotten
+    /// `TOMBSTONE_DAYS` after their feed last listed them.
+    pub fn purge(&self, conn: &mut Connection, now: DateTime<Utc>) -> Result<usize> {
+        let tx = conn.transaction()?;
+        let ids = self.expired_items(&tx, now)?;
+        let rarray = Rc::new(
+            ids.iter()
+                .cloned()
+                .map(rusqlite::types::Value::from)
+                .collect::<Vec<_>>(),
+        );
+
+        tx.execute(
+            r"
+        INSERT INTO `item_tombstone` (feed_id, guid, purged)
+        SELECT `feed_id`, COALESCE(`guid`, `url`), ?2 FROM `item`
+        WHERE `id` IN rarray(?1) AND COALESCE(`guid`, `url`) IS NOT NULL",
+            params![rarray, now],
+        )?;
+        let purged = tx.execute(
+            "DELETE FROM `item` WHERE `id` IN rarray(?1)",
+            params![rarray],
+        )?;
+        tx.execute(
+            "DELETE FROM `item_tombstone` WHERE `purged` < ?1",
+            params![now - chrono::Duration::days(Self::TOMBSTONE_DAYS)],
+        )?;
+        tx.commit()?;
+        Ok(purged)
+    }
+}
+
+/// An earlier version of an item, kept when the entry gets updated.
+#[derive(Debug)]
+pub struct ItemRevision {
+    pub id: u32,
+    pub item_id: u32,
+    pub title: String,
+    pub author: String,
+    pub html: String,
+    pub url: String,
+    pub updated_on_time: Option<DateTime<Utc>>,
+    /// When this version was replaced by a newer one
+    pub replaced_on_time: DateTime<Utc>,
+}
+
+impl ItemRevision {
+    /// Returns revisions of an item, oldest first.
+    pub fn by_item(conn: &Connection, item_id: u32) -> Result<Vec<Self>> {
+        Ok(conn
+            .prepare("SELECT * FROM `item_revision` WHERE `item_id` = ?1 ORDER BY `id`")?
+            .query_map(params![item_id], Self::from_row)?
+            .collect::<Result<_, _>>()?)
+    }
+}
+
+impl Model for ItemRevision {
+    const TABLE: &'static str = "item_revision";
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            item_id: row.get(1)?,
+            title: row.get(2)?,
+            author: row.get(3)?,
+            html: row.get(4)?,
+            url: row.get(5)?,
+            updated_on_time: row.get(6)?,
+            replaced_on_time: row.get(7)?,
+        })
+    }
+
+    fn set_id(&mut self, id: u32) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> u32 {
+        self.id
+    }
+}
+
+/// A link found in item contents, aggregated by url for Fever's "Hot links".
+#[derive(Debug, Serialize)]
+pub struct Link {
+    pub id: u32,
+    pub feed_id: u32,
+    pub item_id: u32,
+    /// Weighted count of feeds and items linking to `url` within the requested window
+    pub temperature: f64,
+    pub is_item: bool,
+    pub is_local: bool,
+    pub is_saved: bool,
+    pub title: String,
+    pub url: String,
+    #[serde(serialize_with = "FeedGroup::serialize_json")]
+    pub item_ids: Vec<u32>,
+}
+
+impl Link {
+    /// Each distinct feed mentioning a link weighs this many items.
+    const FEED_WEIGHT: f64 = 10.0;
+
+    /// Extracts outbound links from the contents of `items` and records them.
+    pub fn insert_from_items(conn: &Connection, items: &[Item]) -> Result<()> {
+        let mut stmt = conn.prepare(
+            r"
+        INSERT INTO `link` (feed_id, item_id, title, url, is_local, created)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+
+        for item in items.iter() {
+            let base = url::Url::parse(&item.url).ok();
+            for (href, title) in crate::find::find_anchors(item.html.as_bytes()) {
+                let url = match base
+                    .as_ref()
+                    .map_or_else(|| url::Url::parse(&href), |base| base.join(&href))
+                {
+                    Ok(url) if url.scheme() == "http" || url.scheme() == "https" => url,
+                    _ => continue,
+                };
+                if url.as_str() == item.url {
+                    continue;
+                }
+                let is_local = base
+                    .as_ref()
+                    .map(|base| base.host_str() == url.host_str())
+                    .unwrap_or(false);
+
+                stmt.execute(params![
+                    item.feed_id,
+                    item.id,
+                    title,
+                    url.as_str(),
+                    is_local,
+                    item.created_on_time,
+                ])?;
+            }
+        }
+
+        stmt.finalize()?;
         Ok(())
-    });
-    let pool = r2d2::Pool::new(manager)?;
+    }
 
-    {
-        let conn = pool.get()?;
+    /// Selects the hottest links mentioned by items created within `(start, end]`, 50 per page.
+    pub fn hot(
+        conn: &Connection,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        page: u32,
+    ) -> Result<Vec<Self>> {
+        Ok(conn
+            .prepare(
+                r"
+        SELECT first.id, first.feed_id, first.item_id, hot.temperature,
+            EXISTS(SELECT 1 FROM `item` WHERE `item`.`url` = first.url),
+            first.is_local,
+            EXISTS(SELECT 1 FROM `item` WHERE `item`.`url` = first.url AND `is_saved` = 1),
+            first.title, first.url, hot.item_ids
+        FROM (
+            SELECT MIN(`id`) AS id,
+                COUNT(DISTINCT `feed_id`) * ?3 + COUNT(*) AS temperature,
+                GROUP_CONCAT(`item_id`) AS item_ids
+            FROM `link`
+            WHERE `created` > ?1 AND `created` <= ?2
+            GROUP BY `url`
+        ) AS hot
+        JOIN `link` AS first ON first.id = hot.id
+        ORDER BY hot.temperature DESC, first.id DESC
+        LIMIT 50 OFFSET ?4",
+            )?
+            .query_map(
+                params![
+                    start,
+                    end,
+                    Self::FEED_WEIGHT,
+                    page.saturating_sub(1).saturating_mul(50)
+                ],
+                |row| {
+                    Ok(Self {
+                        id: row.get(0)?,
+                        feed_id: row.get(1)?,
+                        item_id: row.get(2)?,
+                        temperature: row.get(3)?,
+                        is_item: row.get(4)?,
+                        is_local: row.get(5)?,
+                        is_saved: row.get(6)?,
+                        title: row.get(7)?,
+                        url: row.get(8)?,
+                        item_ids: row
+                            .get::<_, String>(9)?
+                            .split(',')
+                            .filter_map(|id| id.parse().ok())
+                            .collect(),
+                    })
+                },
+            )?
+            .collect::<Result<_, _>>()?)
+    }
+}
+
+fn timestamp_to_datetime(timestamp: Option<u32>) -> Option<DateTime<Utc>> {
+    timestamp.map(|ts| Utc.timestamp(ts as i64, 0))
+}
+
+/// A step in the evolution of the database schema. Applying every migration in order to an
+/// empty database yields the current schema.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    up: fn(&Connection) -> Result<()>,
+}
+
+/// Schema migrations, ordered by version. Released migrations must never be changed, only
+/// followed by new ones.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema (lares 0.2.1)",
+        up: migrate_initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "crawl state, entry ids, item revisions and links",
+        up: migrate_crawl_state,
+    },
+    Migration {
+        version: 3,
+        description: "foreign keys and indexes",
+        up: migrate_foreign_keys,
+    },
+    Migration {
+        version: 4,
+        description: "tombstones of purged items",
+        up: migrate_item_tombstones,
+    },
+    Migration {
+        version: 5,
+        description: "full-text search index of items",
+        up: migrate_item_search,
+    },
+    Migration {
+        version: 6,
+        description: "nested groups",
+        up: migrate_group_parent,
+    },
+    Migration {
+        version: 7,
+        description: "spark flags following group membership",
+        up: migrate_spark_triggers,
+    },
+];
+
+fn migrate_initial_schema(conn: &Connection) -> Result<()> {
+    // databases created before migrations existed already have these tables
+    conn.execute_batch(
+        r#"
+    CREATE TABLE IF NOT EXISTS `group` (
+        id INTEGER PRIMARY KEY,
+        title TEXT UNIQUE ON CONFLICT IGNORE
+    );
+    CREATE TABLE IF NOT EXISTS `feed` (
+        id INTEGER PRIMARY KEY,
+        title TEXT,
+        url TEXT,
+        site_url TEXT,
+        is_spark BOOLEAN,
+        last_updated DATETIME
+    );
+    CREATE TABLE IF NOT EXISTS `feed_group` (
+        id INTEGER PRIMARY KEY,
+        group_id INTEGER,
+        feed_id INTEGER,
+        UNIQUE(group_id, feed_id) ON CONFLICT IGNORE
+    );
+    CREATE TABLE IF NOT EXISTS `favicon` (
+        id INTEGER PRIMARY KEY,
+        data BLOB
+    );
+    CREATE TABLE IF NOT EXISTS `item` (
+        id INTEGER PRIMARY KEY,
+        feed_id INTEGER,
+        title TEXT,
+        author TEXT,
+        html BLOB,
+        url TEXT,
+        is_saved BOOLEAN,
+        is_read BOOLEAN,
+        created DATETIME
+    );
+    "#,
+    )?;
+    Ok(())
+}
+
+fn migrate_crawl_state(conn: &Connection) -> Result<()> {
+    rebuild_table(
+        conn,
+        "feed",
+        r#"
+    CREATE TABLE `feed` (
+        id INTEGER PRIMARY KEY,
+        title TEXT,
+        url TEXT,
+        site_url TEXT,
+        is_spark BOOLEAN,
+        last_updated DATETIME,
+        favicon_id INTEGER DEFAULT 0,
+        favicon_checked DATETIME,
+        etag TEXT,
+        last_modified TEXT,
+        last_status INTEGER,
+        fetch_interval INTEGER,
+        update_hint INTEGER,
+        next_fetch DATETIME,
+        last_error TEXT,
+        error_count INTEGER DEFAULT 0,
+        last_success DATETIME
+    )
+    "#,
+    )?;
+    rebuild_table(
+        conn,
+        "item",
+        r#"
+    CREATE TABLE `item` (
+        id INTEGER PRIMARY KEY,
+        feed_id INTEGER,
+        title TEXT,
+        author TEXT,
+        html BLOB,
+        url TEXT,
+        is_saved BOOLEAN,
+        is_read BOOLEAN,
+        created DATETIME,
+        read_on DATETIME,
+        guid TEXT,
+        date_source TEXT,
+        updated DATETIME,
+        content_hash TEXT,
+        UNIQUE(feed_id, guid) ON CONFLICT IGNORE
+    )
+    "#,
+    )?;
+    conn.execute_batch(
+        r#"
+    CREATE TABLE IF NOT EXISTS `item_revision` (
+        id INTEGER PRIMARY KEY,
+        item_id INTEGER,
+        title TEXT,
+        author TEXT,
+        html BLOB,
+        url TEXT,
+        updated DATETIME,
+        replaced DATETIME
+    );
+    CREATE TABLE IF NOT EXISTS `link` (
+        id INTEGER PRIMARY KEY,
+        feed_id INTEGER,
+        item_id INTEGER,
+        title TEXT,
+        url TEXT,
+        is_local BOOLEAN,
+        created DATETIME,
+        UNIQUE(item_id, url) ON CONFLICT IGNORE
+    );
+    "#,
+    )?;
+    // items used to be told apart by their url
+    conn.execute_batch("UPDATE `item` SET `guid` = `url` WHERE `guid` IS NULL")?;
+    Ok(())
+}
+
+fn migrate_foreign_keys(conn: &Connection) -> Result<()> {
+    // rows left behind by deleted feeds would violate the new constraints
+    conn.execute_batch(
+        r#"
+    DELETE FROM `feed_group` WHERE `feed_id` NOT IN (SELECT `id` FROM `feed`)
+        OR `group_id` NOT IN (SELECT `id` FROM `group`);
+    DELETE FROM `item` WHERE `feed_id` NOT IN (SELECT `id` FROM `feed`);
+    DELETE FROM `item_revision` WHERE `item_id` NOT IN (SELECT `id` FROM `item`);
+    DELETE FROM `link` WHERE `item_id` NOT IN (SELECT `id` FROM `item`);
+    "#,
+    )?;
+
+    replace_table(
+        conn,
+        "feed_group",
+        r#"
+        id INTEGER PRIMARY KEY,
+        group_id INTEGER REFERENCES `group` (id) ON DELETE CASCADE,
+        feed_id INTEGER REFERENCES `feed` (id) ON DELETE CASCADE,
+        UNIQUE(group_id, feed_id) ON CONFLICT IGNORE
+    "#,
+    )?;
+    replace_table(
+        conn,
+        "item",
+        r#"
+        id INTEGER PRIMARY KEY,
+        feed_id INTEGER REFERENCES `feed` (id) ON DELETE CASCADE,
+        title TEXT,
+        author TEXT,
+        html BLOB,
+        url TEXT,
+        is_saved BOOLEAN,
+        is_read BOOLEAN,
+        created DATETIME,
+        read_on DATETIME,
+        guid TEXT,
+        date_source TEXT,
+        updated DATETIME,
+        content_hash TEXT,
+        UNIQUE(feed_id, guid) ON CONFLICT IGNORE
+    "#,
+    )?;
+    replace_table(
+        conn,
+        "item_revision",
+        r#"
+        id INTEGER PRIMARY KEY,
+        item_id INTEGER REFERENCES `item` (id) ON DELETE CASCADE,
+        title TEXT,
+        author TEXT,
+        html BLOB,
+        url TEXT,
+        updated DATETIME,
+        replaced DATETIME
+    "#,
+    )?;
+    replace_table(
+        conn,
+        "link",
+        r#"
+        id INTEGER PRIMARY KEY,
+        feed_id INTEGER REFERENCES `feed` (id) ON DELETE CASCADE,
+        item_id INTEGER REFERENCES `item` (id) ON DELETE CASCADE,
+        title TEXT,
+        url TEXT,
+        is_local BOOLEAN,
+        created DATETIME,
+        UNIQUE(item_id, url) ON CONFLICT IGNORE
+    "#,
+    )?;
+
+    conn.execute_batch(
+        r#"
+    CREATE INDEX `feed_group_feed_id` ON `feed_group` (feed_id);
+    CREATE INDEX `item_feed_id` ON `item` (feed_id);
+    CREATE INDEX `item_is_read` ON `item` (is_read);
+    CREATE INDEX `item_is_saved` ON `item` (is_saved);
+    CREATE INDEX `item_url` ON `item` (url);
+    CREATE INDEX `item_revision_item_id` ON `item_revision` (item_id);
+    CREATE INDEX `link_feed_id` ON `link` (feed_id);
+    "#,
+    )?;
+    Ok(())
+}
+
+fn migrate_item_tombstones(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+    CREATE TABLE `item_tombstone` (
+        feed_id INTEGER REFERENCES `feed` (id) ON DELETE CASCADE,
+        guid TEXT,
+        purged DATETIME,
+        PRIMARY KEY (feed_id, guid) ON CONFLICT IGNORE
+    );
+    "#,
+    )?;
+    Ok(())
+}
+
+fn migrate_item_search(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+    CREATE VIRTUAL TABLE `item_search` USING fts5(title, author, content);
+    CREATE TRIGGER `item_search_delete` AFTER DELETE ON `item`
+    BEGIN
+        DELETE FROM `item_search` WHERE `rowid` = old.id;
+    END;
+    "#,
+    )?;
+
+    // new and revised items are indexed by `Item::index`, which strips tags in Rust
+    let mut select = conn.prepare("SELECT id, title, author, html FROM `item`")?;
+    let mut insert = conn.prepare(
+        "INSERT INTO `item_search` (rowid, title, author, content) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    let mut rows = select.query(NO_PARAMS)?;
+    while let Some(row) = rows.next()? {
+        let html: String = row.get(3)?;
+        insert.execute(params![
+            row.get::<_, u32>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            crate::find::strip_tags(&html)
+        ])?;
+    }
+    Ok(())
+}
+
+fn migrate_group_parent(conn: &Connection) -> Result<()> {
+    // titles become unique among the children of a group rather than across all groups. NULL
+    // never equals itself, so top-level groups are indexed under parent 0.
+    replace_table(
+        conn,
+        "group",
+        r"
+        id INTEGER PRIMARY KEY,
+        title TEXT,
+        parent_id INTEGER REFERENCES `group` (`id`) ON DELETE SET NULL",
+    )?;
+    conn.execute_batch(
+        r#"
+    CREATE INDEX `group_parent_id` ON `group` (`parent_id`);
+    CREATE UNIQUE INDEX `group_parent_title` ON `group` (IFNULL(`parent_id`, 0), `title`);
+    "#,
+    )?;
+    Ok(())
+}
+
+fn migrate_spark_triggers(conn: &Connection) -> Result<()> {
+    // also covers memberships deleted along with their group
+    conn.execute_batch(
+        r#"
+    CREATE TRIGGER `feed_group_insert` AFTER INSERT ON `feed_group`
+    BEGIN
+        UPDATE `feed` SET `is_spark` = 0 WHERE `id` = new.feed_id;
+    END;
+    CREATE TRIGGER `feed_group_delete` AFTER DELETE ON `feed_group`
+    BEGIN
+        UPDATE `feed` SET `is_spark` = NOT EXISTS (
+            SELECT 1 FROM `feed_group` WHERE `feed_id` = old.feed_id
+        ) WHERE `id` = old.feed_id;
+    END;
+    UPDATE `feed` SET `is_spark` = NOT EXISTS (
+        SELECT 1 FROM `feed_group` WHERE `feed_id` = `feed`.`id`
+    );
+    "#,
+    )?;
+    Ok(())
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    Ok(conn
+        .prepare(&format!("PRAGMA table_info(`{}`)", table))?
+        .query_map(NO_PARAMS, |row| row.get(1))?
+        .collect::<Result<_, _>>()?)
+}
+
+/// Replaces `table` with a table created by `definition`, copying over the columns both have
+/// in common. SQLite can't add constraints to an existing table, and columns keep their
+/// position this way.
+fn rebuild_table(conn: &Connection, table: &str, definition: &str) -> Result<()> {
+    let old = format!("{}_old", table);
+    conn.execute_batch(&format!("ALTER TABLE `{}` RENAME TO `{}`", table, old))?;
+    conn.execute_batch(definition)?;
+
+    let columns = table_columns(conn, table)?;
+    let shared = table_columns(conn, &old)?
+        .into_iter()
+        .filter(|column| columns.contains(column))
+        .map(|column| format!("`{}`", column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    conn.execute_batch(&format!(
+        "INSERT INTO `{0}` ({1}) SELECT {1} FROM `{2}`; DROP TABLE `{2}`;",
+        table, shared, old
+    ))?;
+    Ok(())
+}
+
+/// Like `rebuild_table`, but creates the new table from `columns` and renames it over `table`
+/// rather than renaming `table` away, since renaming a table also rewrites foreign keys
+/// referencing it. Indexes of `table` are dropped along with it.
+fn replace_table(conn: &Connection, table: &str, columns: &str) -> Result<()> {
+    let new = format!("{}_new", table);
+    conn.execute_batch(&format!("CREATE TABLE `{}` ({})", new, columns))?;
+
+    let columns = table_columns(conn, &new)?;
+    let shared = table_columns(conn, table)?
+        .into_iter()
+        .filter(|column| columns.contains(column))
+        .map(|column| format!("`{}`", column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    conn.execute_batch(&format!(
+        r"
+    INSERT INTO `{1}` ({2}) SELECT {2} FROM `{0}`;
+    DROP TABLE `{0}`;
+    ALTER TABLE `{1}` RENAME TO `{0}`;",
+        table, new, shared
+    ))?;
+    Ok(())
+}
+
+/// Returns the schema version of the database, `0` if it has never been migrated.
+pub fn schema_version(conn: &Connection) -> Result<u32> {
+    Ok(applied_migrations(conn)?
+        .last()
+        .map(|(version, _)| *version)
+        .unwrap_or(0))
+}
+
+/// Lists applied migrations along with the time they were applied, oldest first.
+pub fn applied_migrations(conn: &Connection) -> Result<Vec<(u32, DateTime<Utc>)>> {
+    let exists = conn
+        .query_row(
+            "SELECT 1 FROM `sqlite_master` WHERE `type` = 'table' AND `name` = 'schema_version'",
+            NO_PARAMS,
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !exists {
+        return Ok(Vec::new());
+    }
+
+    Ok(conn
+        .prepare("SELECT `version`, `applied` FROM `schema_version` ORDER BY `version`")?
+        .query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?)
+}
 
-        Group::create_table(&conn)?;
-        Feed::create_table(&conn)?;
-        FeedGroup::create_table(&conn)?;
-        Favicon::create_table(&conn)?;
-        Item::create_table(&conn)?;
+/// Brings the database schema up to date in a single transaction, and returns the migrations
+/// that were applied.
+pub fn migrate(conn: &mut Connection) -> Result<Vec<&'static Migration>> {
+    // dropping rebuilt tables must not cascade, and the pragma is a no-op inside a transaction
+    let foreign_keys = conn.query_row("PRAGMA foreign_keys", NO_PARAMS, |row| {
+        row.get::<_, bool>(0)
+    })?;
+    conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+    let result = migrate_unchecked(conn);
+    if foreign_keys {
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
     }
+    result
+}
+
+fn migrate_unchecked(conn: &mut Connection) -> Result<Vec<&'static Migration>> {
+    let tx = conn.transaction()?;
+    tx.execute_batch(
+        r#"
+    CREATE TABLE IF NOT EXISTS `schema_version` (
+        version INTEGER PRIMARY KEY,
+        applied DATETIME
+    )
+    "#,
+    )?;
+
+    let current = schema_version(&tx)?;
+    let latest = MIGRATIONS.last().map(|m| m.version).unwrap_or(0);
+    if current > latest {
+        return Err(Error::message(format!(
+            "database schema version {} is newer than the latest known version {}",
+            current, latest
+        )));
+    }
+
+    let pending = MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > current)
+        .collect::<Vec<_>>();
+    for migration in pending.iter() {
+        (migration.up)(&tx)?;
+        tx.execute(
+            "INSERT INTO `schema_version` (version, applied) VALUES (?1, ?2)",
+            params![migration.version, Utc::now()],
+        )?;
+    }
+
+    let violation = tx
+        .query_row("PRAGMA foreign_key_check", NO_PARAMS, |row| {
+            row.get::<_, String>(0)
+        })
+        .optional()?;
+    if let Some(table) = violation {
+        return Err(Error::message(format!(
+            "migration left rows violating foreign keys in table `{}`",
+            table
+        )));
+    }
+    tx.commit()?;
+
+    Ok(pending)
+}
+
+/// SQLite settings applied to every pooled connection.
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    /// `WAL` lets readers carry on while the crawler writes
+    pub journal_mode: String,
+    pub synchronous: String,
+    /// How long to wait for a lock held by another connection before failing with `SQLITE_BUSY`
+    pub busy_timeout: Duration,
+    /// Page cache size of each connection. Negative values are in KiB, positive ones in pages.
+    pub cache_size: i64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            journal_mode: Self::DEFAULT_JOURNAL_MODE.to_owned(),
+            synchronous: Self::DEFAULT_SYNCHRONOUS.to_owned(),
+            busy_timeout: Duration::from_millis(Self::DEFAULT_BUSY_TIMEOUT_MS),
+            cache_size: -(Self::DEFAULT_CACHE_SIZE_KIB as i64),
+        }
+    }
+}
+
+impl DatabaseConfig {
+    pub const DEFAULT_JOURNAL_MODE: &'static str = "WAL";
+    pub const DEFAULT_SYNCHRONOUS: &'static str = "NORMAL";
+    pub const DEFAULT_BUSY_TIMEOUT_MS: u64 = 5000;
+    pub const DEFAULT_CACHE_SIZE_KIB: u32 = 16 * 1024;
+
+    fn apply(&self, conn: &Connection) -> rusqlite::Result<()> {
+        conn.busy_timeout(self.busy_timeout)?;
+        conn.pragma_update(None, "journal_mode", &self.journal_mode)?;
+        conn.pragma_update(None, "synchronous", &self.synchronous)?;
+        conn.pragma_update(None, "cache_size", &self.cache_size)?;
+        conn.pragma_update(None, "foreign_keys", &true)?;
+        Ok(())
+    }
+}
 
+/// Opens the database without touching its schema.
+pub fn open_pool(
+    path: &Path,
+    config: DatabaseConfig,
+) -> Result<r2d2::Pool<SqliteConnectionManager>> {
+    let manager = SqliteConnectionManager::file(path).with_init(move |c| {
+        rusqlite::vtab::array::load_module(&c)?;
+        config.apply(c)
+    });
+    Ok(r2d2::Pool::new(manager)?)
+}
+
+/// Opens the database and applies pending migrations.
+pub fn get_pool(
+    path: &Path,
+    config: DatabaseConfig,
+) -> Result<r2d2::Pool<SqliteConnectionManager>> {
+    let pool = open_pool(path, config)?;
+    {
+        let mut conn = pool.get()?;
+        migrate(&mut conn)?;
+    }
     Ok(pool)
 }
 
@@ -642,12 +2266,39 @@ mod test {
         Group::new(format!("group {}", i))
     }
 
+    fn make_test_item(feed_id: u32, created: i64) -> Item {
+        Item {
+            id: 0,
+            feed_id,
+            title: format!("item {}", created),
+            author: "author".to_owned(),
+            html: "<p>content</p>".to_owned(),
+            url: format!("http://{}.example.com/{}", feed_id, created),
+            is_saved: false,
+            is_read: false,
+            created_on_time: Utc.timestamp(created, 0),
+            read_on_time: None,
+            guid: format!("{}-{}", feed_id, created),
+            date_source: DateSource::Published,
+            updated_on_time: None,
+            content_hash: Item::content_hash(&format!("item {}", created), "<p>content</p>"),
+        }
+    }
+
+    fn read_ids(conn: &Connection) -> Vec<u32> {
+        let unread = Item::unread(conn).unwrap();
+        Item::all(conn)
+            .unwrap()
+            .into_iter()
+            .map(|item| item.id)
+            .filter(|id| !unread.contains(id))
+            .collect()
+    }
+
     #[test]
     fn test_group() -> Result<()> {
-        let conn = Connection::open_in_memory().unwrap();
-        Group::create_table(&conn).unwrap();
-        Feed::create_table(&conn).unwrap();
-        FeedGroup::create_table(&conn).unwrap();
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
 
         // prepare
         let group = make_test_group(1).insert(&conn).unwrap();
@@ -674,11 +2325,9 @@ mod test {
 
     #[test]
     fn test_feed_group() {
-        let conn = Connection::open_in_memory().unwrap();
+        let mut conn = Connection::open_in_memory().unwrap();
         rusqlite::vtab::array::load_module(&conn).unwrap();
-        Group::create_table(&conn).unwrap();
-        Feed::create_table(&conn).unwrap();
-        FeedGroup::create_table(&conn).unwrap();
+        migrate(&mut conn).unwrap();
 
         for group_id in 1..3 {
             let group = make_test_group(group_id).insert(&conn).unwrap();
@@ -703,4 +2352,852 @@ mod test {
         let feed = Feed::get(&conn, feed_id).unwrap();
         assert!(feed.is_spark);
     }
+
+    #[test]
+    fn test_feed_read() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let feed1 = make_test_feed(1).insert(&conn).unwrap();
+        let feed2 = make_test_feed(2).insert(&conn).unwrap();
+        Item::insert_multi(
+            &conn,
+            vec![
+                make_test_item(feed1.id, 1000),
+                make_test_item(feed1.id, 2000),
+                make_test_item(feed1.id, 3000),
+                make_test_item(feed2.id, 1000),
+            ],
+        )
+        .unwrap();
+
+        // items created exactly at `before` are marked as well
+        assert_eq!(feed1.read(&conn, Some(2000)).unwrap(), 2);
+        assert_eq!(read_ids(&conn), vec![1, 2]);
+
+        assert_eq!(feed1.read(&conn, None).unwrap(), 1);
+        assert_eq!(read_ids(&conn), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_group_read() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let group1 = make_test_group(1).insert(&conn).unwrap();
+        let group2 = make_test_group(2).insert(&conn).unwrap();
+        let feed1 = group1
+            .add_feed(&conn, make_test_feed(1).insert(&conn).unwrap())
+            .unwrap();
+        let feed2 = group1
+            .add_feed(&conn, make_test_feed(2).insert(&conn).unwrap())
+            .unwrap();
+        let feed3 = group2
+            .add_feed(&conn, make_test_feed(3).insert(&conn).unwrap())
+            .unwrap();
+        Item::insert_multi(
+            &conn,
+            vec![
+                make_test_item(feed1.id, 1000),
+                make_test_item(feed2.id, 2000),
+                make_test_item(feed2.id, 2001),
+                make_test_item(feed3.id, 1000),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(group1.read(&conn, Some(1999)).unwrap(), 1);
+        assert_eq!(read_ids(&conn), vec![1]);
+
+        // only unread items count as newly marked
+        assert_eq!(group1.read(&conn, Some(2000)).unwrap(), 1);
+        assert_eq!(read_ids(&conn), vec![1, 2]);
+
+        group1.read(&conn, None).unwrap();
+        assert_eq!(read_ids(&conn), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_kindling_sparks_read() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        rusqlite::vtab::array::load_module(&conn).unwrap();
+        migrate(&mut conn).unwrap();
+
+        let group1 = make_test_group(1).insert(&conn).unwrap();
+        let group2 = make_test_group(2).insert(&conn).unwrap();
+        let grouped = group1
+            .add_feed(&conn, make_test_feed(1).insert(&conn).unwrap())
+            .unwrap();
+        let spark = make_test_feed(2).insert(&conn).unwrap();
+        let ungrouped = group2
+            .add_feed(&conn, make_test_feed(3).insert(&conn).unwrap())
+            .unwrap();
+        FeedGroup::get_by_group(&conn, group2.id)
+            .unwrap()
+            .delete(&conn)
+            .unwrap();
+        Item::insert_multi(
+            &conn,
+            vec![
+                make_test_item(grouped.id, 1000),
+                make_test_item(spark.id, 1000),
+                make_test_item(spark.id, 2000),
+                make_test_item(ungrouped.id, 1000),
+            ],
+        )
+        .unwrap();
+
+        // sparks include feeds that were removed from their group
+        assert_eq!(Group::read_sparks(&conn, Some(1000)).unwrap(), 2);
+        assert_eq!(read_ids(&conn), vec![2, 4]);
+
+        assert_eq!(Group::read_kindling(&conn, Some(1999)).unwrap(), 1);
+        assert_eq!(read_ids(&conn), vec![1, 2, 4]);
+
+        Group::read_kindling(&conn, None).unwrap();
+        assert!(Item::unread(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unread_recently_read() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let feed = make_test_feed(1).insert(&conn).unwrap();
+        Item::insert_multi(
+            &conn,
+            (1..=4).map(|i| make_test_item(feed.id, i * 1000)).collect(),
+        )
+        .unwrap();
+
+        let item = Item::get(&conn, 1).unwrap().read(&conn).unwrap();
+        assert!(item.read_on_time.is_some());
+        feed.read(&conn, Some(3000)).unwrap();
+        assert_eq!(read_ids(&conn), vec![1, 2, 3]);
+
+        // undo the feed-wide pass first, then the single item
+        assert_eq!(Item::unread_recently_read(&conn).unwrap(), 2);
+        assert_eq!(read_ids(&conn), vec![1]);
+        assert_eq!(Item::unread_recently_read(&conn).unwrap(), 1);
+        assert!(read_ids(&conn).is_empty());
+        assert_eq!(Item::unread_recently_read(&conn).unwrap(), 0);
+
+        let item = Item::get(&conn, 4).unwrap().read(&conn).unwrap();
+        let item = item.mark_unread(&conn).unwrap();
+        assert!(!item.is_read);
+        assert!(Item::get(&conn, 4).unwrap().read_on_time.is_none());
+    }
+
+    #[test]
+    fn test_hot_links() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let feeds = (1..=3)
+            .map(|i| make_test_feed(i).insert(&conn).unwrap())
+            .collect::<Vec<_>>();
+        let make_item = |feed: &Feed, created: i64, html: &str| Item {
+            html: html.to_owned(),
+            ..make_test_item(feed.id, created)
+        };
+        let items = Item::insert_multi(
+            &conn,
+            vec![
+                make_item(
+                    &feeds[0],
+                    1000,
+                    r#"<p><a href="https://hot.example.com/">Hot &amp; new</a></p>"#,
+                ),
+                make_item(
+                    &feeds[1],
+                    2000,
+                    r#"<a href="https://hot.example.com/">hot</a> <a href="/local">local</a>"#,
+                ),
+                make_item(
+                    &feeds[2],
+                    3000,
+                    r#"<a href="https://hot.example.com/">hot</a><a href="https://warm.example.com/">warm</a>"#,
+                ),
+                make_item(
+                    &feeds[2],
+                    3001,
+                    r#"<a href="https://warm.example.com/">warm</a><a href="mailto:x@example.com">x</a>"#,
+                ),
+                make_item(&feeds[0], 9000, r#"<a href="https://old.example.com/">old"#),
+            ],
+        )
+        .unwrap()
+        .items;
+        Link::insert_from_items(&conn, &items).unwrap();
+
+        let links = Link::hot(&conn, Utc.timestamp(0, 0), Utc.timestamp(5000, 0), 1).unwrap();
+        assert_eq!(links.len(), 3);
+
+        assert_eq!(links[0].url, "https://hot.example.com/");
+        assert_eq!(links[0].title, "Hot & new");
+        assert_eq!(links[0].item_ids, vec![1, 2, 3]);
+        assert_eq!(links[0].temperature, 33.0);
+
+        // two items of a single feed are not as hot as three feeds
+        assert_eq!(links[1].url, "https://warm.example.com/");
+        assert_eq!(links[1].temperature, 12.0);
+
+        assert_eq!(links[2].url, "http://2.example.com/local");
+        assert!(links[2].is_local);
+        assert!(!links[0].is_local);
+
+        let links = Link::hot(&conn, Utc.timestamp(2000, 0), Utc.timestamp(5000, 0), 1).unwrap();
+        assert_eq!(links[0].url, "https://warm.example.com/");
+        assert_eq!(links[1].url, "https://hot.example.com/");
+        assert_eq!(links[1].item_ids, vec![3]);
+        assert!(
+            Link::hot(&conn, Utc.timestamp(0, 0), Utc.timestamp(5000, 0), 2)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_crawl_interval() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let hour = 60 * 60;
+        let mut feed = make_test_feed(1).insert(&conn).unwrap();
+        assert_eq!(feed.crawl_interval(&conn, hour).unwrap(), hour);
+
+        // one item every 3 hours
+        let items = (0..5)
+            .map(|i| make_test_item(feed.id, 1_600_000_000 + i * 3 * hour as i64))
+            .collect();
+        Item::insert_multi(&conn, items).unwrap();
+        assert_eq!(feed.crawl_interval(&conn, hour).unwrap(), 3 * hour);
+        assert_eq!(feed.crawl_interval(&conn, 4 * hour).unwrap(), 4 * hour);
+
+        feed.update_hint = Some(6 * hour);
+        assert_eq!(feed.crawl_interval(&conn, hour).unwrap(), 6 * hour);
+        feed.update_hint = Some(7 * 24 * hour);
+        assert_eq!(
+            feed.crawl_interval(&conn, hour).unwrap(),
+            Feed::MAX_CRAWL_INTERVAL
+        );
+
+        let feed = feed.set_fetch_interval(&conn, Some(10 * 60)).unwrap();
+        assert_eq!(feed.crawl_interval(&conn, hour).unwrap(), 10 * 60);
+
+        // newly added feeds are due immediately
+        let other = make_test_feed(2).insert(&conn).unwrap();
+        assert_eq!(Feed::due(&conn, Utc::now()).unwrap().len(), 2);
+
+        let feed = feed.schedule(&conn, hour).unwrap();
+        let due = Feed::due(&conn, Utc::now()).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, other.id);
+
+        let other = other.schedule(&conn, hour).unwrap();
+        assert_eq!(Feed::next_due(&conn).unwrap(), feed.next_fetch);
+        assert!(other.next_fetch > feed.next_fetch);
+        assert!(Feed::due(&conn, Utc::now()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_crawl_backoff() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let hour = 60 * 60;
+        let feed = make_test_feed(1).insert(&conn).unwrap();
+        let feed = feed.record_failure(&conn, "boom").unwrap();
+        let feed = feed.record_failure(&conn, "boom again").unwrap();
+
+        let feed = Feed::get(&conn, feed.id).unwrap();
+        assert_eq!(feed.error_count, 2);
+        assert_eq!(feed.last_error.as_deref(), Some("boom again"));
+        assert_eq!(feed.crawl_interval(&conn, hour).unwrap(), 4 * hour);
+
+        let feed = feed.record_failure(&conn, "boom").unwrap();
+        let feed = feed.record_failure(&conn, "boom").unwrap();
+        let feed = feed.record_failure(&conn, "boom").unwrap();
+        assert_eq!(
+            feed.crawl_interval(&conn, hour).unwrap(),
+            Feed::MAX_CRAWL_INTERVAL
+        );
+
+        let feed = feed.record_success(&conn).unwrap();
+        let feed = Feed::get(&conn, feed.id).unwrap();
+        assert_eq!(feed.error_count, 0);
+        assert!(feed.last_error.is_none());
+        assert!(feed.last_success.is_some());
+        assert_eq!(feed.crawl_interval(&conn, hour).unwrap(), hour);
+    }
+
+    #[test]
+    fn test_item_guid() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        make_test_feed(1).insert(&conn).unwrap();
+        make_test_feed(2).insert(&conn).unwrap();
+
+        let item = make_test_item(1, 1000);
+        let duplicate = Item {
+            url: "http://1.example.com/1000?utm_source=rss".to_owned(),
+            ..make_test_item(1, 1000)
+        };
+        let other_feed = make_test_item(2, 1000);
+        let inserted = Item::insert_multi(&conn, vec![item, duplicate, other_feed]).unwrap();
+        assert_eq!(inserted.skipped, 1);
+        assert_eq!(
+            inserted.items.iter().map(|i| i.id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        let inserted = Item::insert_multi(&conn, vec![make_test_item(1, 1000)]).unwrap();
+        assert!(inserted.items.is_empty());
+        assert_eq!(inserted.skipped, 1);
+        assert_eq!(Item::count(&conn).unwrap(), 2);
+
+        assert_eq!(
+            Item::content_hash("title", "<p>content</p>"),
+            Item::content_hash("title", "<p>content</p>")
+        );
+        assert_ne!(
+            Item::content_hash("title", "<p>content</p>"),
+            Item::content_hash("title", "<p>changed</p>")
+        );
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+
+        let applied = migrate(&mut conn).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(
+            schema_version(&conn).unwrap(),
+            MIGRATIONS.last().unwrap().version
+        );
+        assert!(migrate(&mut conn).unwrap().is_empty());
+
+        // databases from before migrations existed are upgraded in place
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_initial_schema(&conn).unwrap();
+        conn.execute(
+            r"
+            INSERT INTO `feed` (title, url, site_url, is_spark, last_updated)
+            VALUES ('feed', '', '', 1, ?1)",
+            params![Utc.timestamp(1000, 0)],
+        )
+        .unwrap();
+        conn.execute(
+            r"
+            INSERT INTO `item` (feed_id, title, author, html, url, is_saved, is_read, created)
+            VALUES (1, 'old', '', '', '', 0, 1, ?1), (2, 'orphan', '', '', '', 0, 1, ?1)",
+            params![Utc.timestamp(1000, 0)],
+        )
+        .unwrap();
+        migrate(&mut conn).unwrap();
+        let items = Item::all(&conn).unwrap();
+        // items of deleted feeds are dropped
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].title, "old");
+        assert!(items[0].is_read);
+        assert_eq!(items[0].created_on_time, Utc.timestamp(1000, 0));
+
+        // unknown versions are refused rather than mangled
+        conn.execute(
+            "INSERT INTO `schema_version` (version, applied) VALUES (?1, ?2)",
+            params![MIGRATIONS.len() as u32 + 1, Utc::now()],
+        )
+        .unwrap();
+        assert!(migrate(&mut conn).is_err());
+    }
+
+    #[test]
+    fn test_delete_cascade() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("PRAGMA foreign_keys = ON").unwrap();
+        migrate(&mut conn).unwrap();
+
+        let group = make_test_group(1).insert(&conn).unwrap();
+        let feed1 = make_test_feed(1).insert(&conn).unwrap();
+        let feed2 = make_test_feed(2).insert(&conn).unwrap();
+        let feed1 = group.add_feed(&conn, feed1).unwrap();
+        let items = Item::insert_multi(
+            &conn,
+            vec![
+                make_test_item(feed1.id, 1000),
+                make_test_item(feed2.id, 1000),
+            ],
+        )
+        .unwrap()
+        .items;
+        Item {
+            id: items[0].id,
+            ..make_test_item(feed1.id, 1000)
+        }
+        .revise(&conn)
+        .unwrap();
+
+        let feed1 = feed1.delete(&conn).unwrap();
+        assert_eq!(Item::count(&conn).unwrap(), 1);
+        assert_eq!(Item::all(&conn).unwrap()[0].feed_id, feed2.id);
+        assert!(ItemRevision::by_item(&conn, items[0].id)
+            .unwrap()
+            .is_empty());
+        assert!(FeedGroup::all(&conn).unwrap().is_empty());
+
+        // items must belong to a feed
+        assert!(Item::insert_multi(&conn, vec![make_test_item(feed1.id, 2000)]).is_err());
+    }
+
+    fn query_plan(conn: &Connection, sql: &str, params: &[&dyn rusqlite::ToSql]) -> String {
+        conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))
+            .unwrap()
+            .query_map(params, |row| row.get::<_, String>(3))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_item_indexes() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let tx = conn.transaction().unwrap();
+        let feeds = (1..=10)
+            .map(|i| make_test_feed(i).insert(&tx).unwrap())
+            .collect::<Vec<_>>();
+        for feed in feeds.iter() {
+            let items = (0..10_000)
+                .map(|i| Item {
+                    is_read: i % 100 != 0,
+                    ..make_test_item(feed.id, i)
+                })
+                .collect();
+            Item::insert_multi(&tx, items).unwrap();
+        }
+        tx.commit().unwrap();
+        assert_eq!(Item::count(&conn).unwrap(), 100_000);
+
+        // plans of the queries `Item::unread` and `Feed::items` run
+        let plan = query_plan(&conn, UNREAD_ITEMS, &[]);
+        assert!(
+            plan.contains("USING COVERING INDEX item_is_read"),
+            "{}",
+            plan
+        );
+        assert_eq!(Item::unread(&conn).unwrap().len(), 1_000);
+
+        let plan = query_plan(&conn, &format!("{} LIMIT 50", FEED_ITEMS), params![1]);
+        assert!(plan.contains("USING INDEX item_feed_id"), "{}", plan);
+        assert!(!plan.contains("TEMP B-TREE"), "{}", plan);
+        let items = feeds[0].items(&conn, Some(50)).unwrap();
+        assert_eq!(items.len(), 50);
+        assert_eq!(items[0].id, 10_000);
+    }
+
+    #[test]
+    fn test_retention() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        rusqlite::vtab::array::load_module(&conn).unwrap();
+        migrate(&mut conn).unwrap();
+
+        let feed1 = make_test_feed(1).insert(&conn).unwrap();
+        let feed2 = make_test_feed(2).insert(&conn).unwrap();
+        let items = (1..=5)
+            .map(|i| make_test_item(feed1.id, i * 1000))
+            .chain((1..=2).map(|i| make_test_item(feed2.id, i * 1000)))
+            .collect();
+        Item::insert_multi(&conn, items).unwrap();
+        Item::get(&conn, 1).unwrap().save(&conn).unwrap();
+
+        let now = Utc.timestamp(5500, 0);
+        assert!(Retention::default()
+            .expired_items(&conn, now)
+            .unwrap()
+            .is_empty());
+
+        let by_age = Retention {
+            max_age: Some(chrono::Duration::seconds(3000)),
+            ..Default::default()
+        };
+        assert_eq!(by_age.expired_items(&conn, now).unwrap(), vec![1, 2, 6, 7]);
+
+        let by_count = Retention {
+            max_items_per_feed: Some(2),
+            keep_saved: true,
+            ..Default::default()
+        };
+        assert_eq!(by_count.expired_items(&conn, now).unwrap(), vec![2, 3]);
+
+        assert_eq!(by_count.purge(&mut conn, now).unwrap(), 2);
+        assert_eq!(Item::count(&conn).unwrap(), 5);
+        assert_eq!(
+            feed1.purged_guids(&conn).unwrap(),
+            vec!["1-2000".to_owned(), "1-3000".to_owned()]
+                .into_iter()
+                .collect()
+        );
+        assert!(by_count.expired_items(&conn, now).unwrap().is_empty());
+
+        // items stored before guids were recorded are remembered by their url
+        conn.execute("UPDATE `item` SET `guid` = NULL WHERE `id` = 4", NO_PARAMS)
+            .unwrap();
+        let by_age = Retention {
+            max_age: Some(chrono::Duration::seconds(1000)),
+            keep_saved: true,
+            ..Default::default()
+        };
+        assert_eq!(by_age.purge(&mut conn, now).unwrap(), 3);
+        assert_eq!(
+            feed1.purged_guids(&conn).unwrap(),
+            vec!["1-2000", "1-3000", "http://1.example.com/4000"]
+                .into_iter()
+                .map(ToOwned::to_owned)
+                .collect()
+        );
+
+        // guids are forgotten after a while
+        let by_count = Retention {
+            max_items_per_feed: Some(100),
+            ..Default::default()
+        };
+        let later = now + chrono::Duration::days(Retention::TOMBSTONE_DAYS);
+        assert_eq!(by_count.purge(&mut conn, later).unwrap(), 0);
+        assert_eq!(feed1.purged_guids(&conn).unwrap().len(), 3);
+        let later = later + chrono::Duration::seconds(1);
+        assert_eq!(by_count.purge(&mut conn, later).unwrap(), 0);
+        assert!(feed1.purged_guids(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_item_search() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let group = make_test_group(1).insert(&conn).unwrap();
+        let feed1 = make_test_feed(1).insert(&conn).unwrap();
+        let feed2 = make_test_feed(2).insert(&conn).unwrap();
+        let feed1 = group.add_feed(&conn, feed1).unwrap();
+        let make_item = |feed: &Feed, created: i64, title: &str, html: &str| Item {
+            title: title.to_owned(),
+            html: html.to_owned(),
+            ..make_test_item(feed.id, created)
+        };
+        Item::insert_multi(
+            &conn,
+            vec![
+                make_item(
+                    &feed1,
+                    1,
+                    "Async Rust",
+                    "<p>Futures &amp; <b>executors</b></p>",
+                ),
+                make_item(&feed1, 2, "Rust release", "<p>New compiler</p>"),
+                make_item(&feed2, 3, "Gardening", "<p>Rust on <em>tools</em></p>"),
+            ],
+        )
+        .unwrap();
+
+        let search = |text: &str, filter: &ItemFilter| -> Vec<u32> {
+            Item::search(&conn, text, filter, 10)
+                .unwrap()
+                .into_iter()
+                .map(|item| item.id)
+                .collect()
+        };
+        let all = ItemFilter::default();
+
+        assert_eq!(search("executors", &all), vec![1]);
+        assert_eq!(search("futures executors", &all), vec![1]);
+        assert_eq!(search("async compiler", &all), Vec::<u32>::new());
+        assert_eq!(search("b", &all), Vec::<u32>::new());
+        assert_eq!(search("", &all), Vec::<u32>::new());
+        // not parsed as query syntax
+        assert_eq!(search("rust\" OR", &all), Vec::<u32>::new());
+        assert_eq!(search("rust", &all).len(), 3);
+
+        let filter = ItemFilter {
+            group_id: Some(group.id),
+            ..Default::default()
+        };
+        assert_eq!(search("rust", &filter), vec![1, 2]);
+        let filter = ItemFilter {
+            feed_id: Some(feed2.id),
+            ..Default::default()
+        };
+        assert_eq!(search("rust", &filter), vec![3]);
+        Item::get(&conn, 1).unwrap().read(&conn).unwrap();
+        let filter = ItemFilter {
+            unread: true,
+            ..Default::default()
+        };
+        assert_eq!(search("rust", &filter).len(), 2);
+
+        // the index follows revisions and deletions
+        Item {
+            id: 2,
+            ..make_item(&feed1, 2, "Rust release", "<p>New borrow checker</p>")
+        }
+        .revise(&conn)
+        .unwrap();
+        assert_eq!(search("compiler", &all), Vec::<u32>::new());
+        assert_eq!(search("borrow", &all), vec![2]);
+        feed2.delete(&conn).unwrap();
+        assert_eq!(search("gardening", &all), Vec::<u32>::new());
+
+        // writing items needs no functions registered on the connection
+        conn.execute(
+            r"
+            INSERT INTO `item` (feed_id, title, author, html, url, is_saved, is_read, created)
+            VALUES (?1, 'plain', '', '<p>sql</p>', '', 0, 0, ?2)",
+            params![feed1.id, Utc::now()],
+        )
+        .unwrap();
+        conn.execute("UPDATE `item` SET `html` = '' WHERE `id` = 1", NO_PARAMS)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_item_list() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let group = make_test_group(1).insert(&conn).unwrap();
+        let feed1 = make_test_feed(1).insert(&conn).unwrap();
+        let feed2 = make_test_feed(2).insert(&conn).unwrap();
+        let feed1 = group.add_feed(&conn, feed1).unwrap();
+        Item::insert_multi(
+            &conn,
+            vec![
+                make_test_item(feed1.id, 1000),
+                make_test_item(feed1.id, 2000),
+                make_test_item(feed2.id, 3000),
+                make_test_item(feed2.id, 4000),
+            ],
+        )
+        .unwrap();
+        Item::get(&conn, 1).unwrap().read(&conn).unwrap();
+        Item::get(&conn, 4).unwrap().save(&conn).unwrap();
+
+        let list = |filter: &ItemFilter, limit: u32| -> Vec<u32> {
+            Item::list(&conn, filter, limit)
+                .unwrap()
+                .into_iter()
+                .map(|item| item.id)
+                .collect()
+        };
+
+        assert_eq!(list(&ItemFilter::default(), 10), vec![4, 3, 2, 1]);
+        assert_eq!(list(&ItemFilter::default(), 2), vec![4, 3]);
+        let filter = ItemFilter {
+            group_id: Some(group.id),
+            ..Default::default()
+        };
+        assert_eq!(list(&filter, 10), vec![2, 1]);
+        let filter = ItemFilter {
+            feed_id: Some(feed2.id),
+            saved: true,
+            ..Default::default()
+        };
+        assert_eq!(list(&filter, 10), vec![4]);
+        let filter = ItemFilter {
+            unread: true,
+            since: Some(Utc.timestamp(2000, 0)),
+            ..Default::default()
+        };
+        assert_eq!(list(&filter, 10), vec![4, 3, 2]);
+    }
+
+    #[test]
+    fn test_group_tree() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let groups = (1..=4)
+            .map(|i| make_test_group(i).insert(&conn).unwrap())
+            .collect::<Vec<_>>();
+        let child = Group::get(&conn, 2)
+            .unwrap()
+            .set_parent(&conn, Some(&groups[0]))
+            .unwrap();
+        let grandchild = Group::get(&conn, 3)
+            .unwrap()
+            .set_parent(&conn, Some(&child))
+            .unwrap();
+        assert_eq!(grandchild.parent_id, Some(child.id));
+        // no group can be nested in itself
+        for parent in &[&child, &grandchild] {
+            assert!(Group::get(&conn, 2)
+                .unwrap()
+                .set_parent(&conn, Some(parent))
+                .is_err());
+        }
+
+        for (i, group) in groups.iter().enumerate() {
+            let feed = make_test_feed(i as u32 + 1).insert(&conn).unwrap();
+            group.add_feed(&conn, feed).unwrap();
+        }
+        Item::insert_multi(
+            &conn,
+            (1..=4).map(|i| make_test_item(i, i as i64)).collect(),
+        )
+        .unwrap();
+
+        let titles = Group::all_with_paths(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|group| group.title)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            vec![
+                "group 1",
+                "group 1 / group 2",
+                "group 1 / group 2 / group 3",
+                "group 4"
+            ]
+        );
+        let feed_ids = FeedGroup::rolled_up(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|feed_group| (feed_group.group_id, feed_group.feed_ids))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            feed_ids,
+            vec![
+                (1, vec![1, 2, 3]),
+                (2, vec![2, 3]),
+                (3, vec![3]),
+                (4, vec![4])
+            ]
+        );
+        let unread = Group::unread_counts(&conn).unwrap();
+        assert_eq!(
+            unread,
+            vec![(1, 3), (2, 2), (3, 1), (4, 1)].into_iter().collect()
+        );
+
+        let filter = ItemFilter {
+            group_id: Some(child.id),
+            ..Default::default()
+        };
+        let ids = Item::list(&conn, &filter, 10)
+            .unwrap()
+            .into_iter()
+            .map(|item| item.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![3, 2]);
+
+        assert_eq!(child.read(&conn, None).unwrap(), 2);
+        let unread = Group::unread_counts(&conn).unwrap();
+        assert_eq!(unread, vec![(1, 1), (4, 1)].into_iter().collect());
+
+        // subgroups of a deleted group move to the top level
+        Group::get(&conn, 1).unwrap().delete(&conn).unwrap();
+        assert_eq!(Group::get(&conn, 2).unwrap().parent_id, None);
+        assert_eq!(child.children(&conn).unwrap()[0].id, grandchild.id);
+    }
+
+    #[test]
+    fn test_group_titles() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let tech = make_test_group(1).insert(&conn).unwrap();
+        let sports = make_test_group(2).insert(&conn).unwrap();
+        let news = |parent: &Group| Group {
+            parent_id: Some(parent.id),
+            ..Group::new("news".to_owned())
+        };
+        let tech_news = news(&tech).insert(&conn).unwrap();
+        let sports_news = news(&sports).insert(&conn).unwrap();
+        assert!(news(&tech).insert(&conn).is_err());
+        assert!(make_test_group(1).insert(&conn).is_err());
+
+        assert_eq!(
+            Group::get_child(&conn, Some(sports.id), "news")
+                .unwrap()
+                .map(|group| group.id),
+            Some(sports_news.id)
+        );
+        assert!(Group::get_child(&conn, None, "news").unwrap().is_none());
+        assert_eq!(
+            Group::get_by_name(&conn, "group 1 / news").unwrap().id,
+            tech_news.id
+        );
+        assert!(Group::get_by_name(&conn, "group 1 / sports").is_err());
+        assert!(Group::get_by_name(&conn, "news").is_err());
+        assert_eq!(Group::get_by_name(&conn, "group 2").unwrap().id, sports.id);
+
+        assert!(tech_news.rename(&conn, "news".to_owned()).is_ok());
+        assert!(sports_news.set_parent(&conn, Some(&tech)).is_err());
+    }
+
+    #[test]
+    fn test_feed_group_updates() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        rusqlite::vtab::array::load_module(&conn).unwrap();
+        migrate(&mut conn).unwrap();
+
+        let group1 = make_test_group(1).insert(&conn).unwrap();
+        let group2 = make_test_group(2).insert(&conn).unwrap();
+        let feed = make_test_feed(1).insert(&conn).unwrap();
+        let feed = group1.add_feed(&conn, feed).unwrap();
+        let feed = group2.add_feed(&conn, feed).unwrap();
+        assert!(!Feed::get(&conn, feed.id).unwrap().is_spark);
+
+        // still in group 2
+        let feed = group1.remove_feed(&conn, feed).unwrap();
+        assert!(!feed.is_spark);
+        let feed = group2.remove_feed(&conn, feed).unwrap();
+        assert!(feed.is_spark);
+        assert!(group2.remove_feed(&conn, feed).is_err());
+
+        // memberships deleted along with their group
+        let feed = group1
+            .add_feed(&conn, Feed::get(&conn, 1).unwrap())
+            .unwrap();
+        group1.delete(&conn).unwrap();
+        assert!(Feed::get(&conn, feed.id).unwrap().is_spark);
+        let feed = group2.add_feed(&conn, feed).unwrap();
+        FeedGroup::get_by_group(&conn, group2.id)
+            .unwrap()
+            .delete(&conn)
+            .unwrap();
+        assert!(Feed::get(&conn, feed.id).unwrap().is_spark);
+
+        let group2 = group2.rename(&conn, "renamed".to_owned()).unwrap();
+        assert_eq!(Group::get_by_name(&conn, "renamed").unwrap().id, group2.id);
+        let group3 = make_test_group(3).insert(&conn).unwrap();
+        assert!(group3.rename(&conn, "renamed".to_owned()).is_err());
+
+        conn.execute(
+            "UPDATE `feed` SET `etag` = 'tag', `next_fetch` = ?1",
+            params![Utc::now()],
+        )
+        .unwrap();
+        let feed = Feed {
+            title: "new title".to_owned(),
+            ..Feed::get(&conn, feed.id).unwrap()
+        }
+        .update(&conn)
+        .unwrap();
+        assert_eq!(feed.title, "new title");
+        assert_eq!(feed.etag.as_deref(), Some("tag"));
+        assert!(feed.next_fetch.is_some());
+
+        let feed = Feed {
+            url: "http://moved.example.com/feed".to_owned(),
+            ..feed
+        }
+        .update(&conn)
+        .unwrap();
+        assert_eq!(feed.url, "http://moved.example.com/feed");
+        assert_eq!(feed.etag, None);
+        assert_eq!(feed.next_fetch, None);
+    }
 }
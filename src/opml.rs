@@ -1,11 +1,12 @@
+use chrono::Utc;
 use log::{debug, info, warn};
-use quick_xml::events::Event;
-use quick_xml::Reader;
-use std::path::Path;
-use std::{collections::HashMap, io::BufRead};
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::io::{BufRead, Write};
+use std::{collections::HashMap, path::Path};
 
 use crate::error::Result;
-use crate::model::Feed;
+use crate::model::{Feed, Group};
 use crate::remote::RemoteFeed;
 
 #[derive(Debug)]
@@ -13,6 +14,9 @@ pub struct ImportedFeed {
     rss_url: String,
     title: Option<String>,
     site_url: Option<String>,
+    /// Paths of the groups this feed is listed in, from the top-level group down to the group
+    /// itself; none for Sparks
+    groups: Vec<Vec<String>>,
 }
 
 impl ImportedFeed {
@@ -21,9 +25,18 @@ impl ImportedFeed {
             rss_url,
             title,
             site_url,
+            groups: Vec::new(),
         }
     }
 
+    pub fn url(&self) -> &str {
+        &self.rss_url
+    }
+
+    pub fn groups(&self) -> &[Vec<String>] {
+        &self.groups
+    }
+
     /// Update title and site_url from rss_url when they are unspecified
     pub async fn update(&mut self) -> Result<()> {
         if self.title.is_some() && self.site_url.is_some() {
@@ -91,20 +104,54 @@ impl From<ImportedFeed> for Feed {
     }
 }
 
-type ImportResult = Result<Vec<(Option<String>, Vec<ImportedFeed>)>>;
+type ImportResult = Result<Vec<ImportedFeed>>;
 
 pub fn from_file(path: &Path) -> ImportResult {
     let reader = Reader::from_file(&path)?;
     from_reader(reader)
 }
 
+/// An `<outline>` element which has not been closed yet.
+enum Outline {
+    /// Folder, named after its `title` (or `text`)
+    Folder(Option<String>),
+    /// Feed outline which has children, which are ignored
+    Feed,
+}
+
+type Attributes = HashMap<Vec<u8>, Vec<u8>>;
+
+fn attributes(e: &BytesStart) -> Attributes {
+    e.attributes()
+        .filter_map(|attr| {
+            if let Ok(attr) = attr {
+                let value = match attr.unescaped_value() {
+                    Ok(value) => value.into_owned(),
+                    Err(_) => attr.value.clone().into_owned(),
+                };
+                Some((attr.key.to_vec(), value))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn attribute(attrs: &Attributes, key: &[u8]) -> Option<String> {
+    attrs
+        .get(key)
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+}
+
+/// Walks the outline tree. Nested folders become nested groups, and so do the paths listed in
+/// the `category` attribute of a feed. A feed listed several times is imported once, belonging
+/// to every group it was found in.
 fn from_reader<B: BufRead>(mut reader: Reader<B>) -> ImportResult {
     reader.trim_text(true);
 
     let mut buf = Vec::new();
-    let mut feeds: Option<Vec<ImportedFeed>> = None;
-    let mut group: Option<String> = None;
-    let mut result: Vec<(Option<String>, Vec<ImportedFeed>)> = Vec::new();
+    let mut outlines: Vec<Outline> = Vec::new();
+    let mut feeds: Vec<ImportedFeed> = Vec::new();
 
     loop {
         match reader.read_event(&mut buf) {
@@ -115,35 +162,21 @@ fn from_reader<B: BufRead>(mut reader: Reader<B>) -> ImportResult {
                     continue;
                 }
 
-                let attrs = e
-                    .attributes()
-                    .filter_map(|attr| {
-                        if let Ok(attr) = attr {
-                            Some((attr.key, attr.value))
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<HashMap<_, _>>();
-
-                if group.is_some() {
-                    warn!("possible malformed OPML file: previous group tag is not closed.");
-                    continue;
-                }
-
-                if let Some(title) = attrs.get(&b"title"[..]).or_else(|| attrs.get(&b"text"[..])) {
-                    let name = String::from_utf8_lossy(title).into_owned();
-                    info!("processing group: {}", &name);
-                    group = Some(name);
+                let attrs = attributes(e);
+                if attrs.contains_key(&b"xmlUrl"[..]) {
+                    add_feed(&mut feeds, &attrs, &outlines);
+                    outlines.push(Outline::Feed);
+                } else {
+                    let name = attribute(&attrs, b"title").or_else(|| attribute(&attrs, b"text"));
+                    info!("processing group: {:?}", name);
+                    outlines.push(Outline::Folder(name));
                 }
             }
             Ok(Event::End(ref e)) => {
                 debug!("end tag </{}>", String::from_utf8_lossy(e.name()));
-                if e.name() == b"outline" {
-                    if feeds.as_ref().map(|x| !x.is_empty()).unwrap_or(false) {
-                        info!("processed group: {:?}", group.as_ref());
-                        result.push((group.take(), feeds.take().unwrap()));
-                    }
+
+                if e.name() == b"outline" && outlines.pop().is_none() {
+                    warn!("possible malformed OPML file: unexpected closing outline tag.");
                 }
             }
             Ok(Event::Empty(ref e)) => {
@@ -154,48 +187,16 @@ fn from_reader<B: BufRead>(mut reader: Reader<B>) -> ImportResult {
                     continue;
                 }
 
-                let attrs = e
-                    .attributes()
-                    .filter_map(|attr| {
-                        if let Ok(attr) = attr {
-                            Some((attr.key, attr.value))
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<HashMap<_, _>>();
-
-                if feeds.is_none() {
-                    feeds = Some(Vec::new());
+                let attrs = attributes(e);
+                if attrs.contains_key(&b"xmlUrl"[..]) {
+                    add_feed(&mut feeds, &attrs, &outlines);
+                } else {
+                    warn!(
+                        "outline item does not contain feed URL, skipping: <{} {} />",
+                        tagname,
+                        String::from_utf8_lossy(e.attributes_raw()),
+                    );
                 }
-
-                let url = match attrs.get(&b"xmlUrl"[..]) {
-                    Some(url) => url,
-                    None => {
-                        warn!(
-                            "outline item does not contain feed URL, skipping: <{} {} />",
-                            tagname,
-                            String::from_utf8_lossy(e.attributes_raw()),
-                        );
-                        continue;
-                    }
-                };
-
-                let title = attrs.get(&b"title"[..]).or_else(|| attrs.get(&b"text"[..]));
-                let site_url = attrs.get(&b"htmlUrl"[..]);
-
-                let rss_url = String::from_utf8_lossy(url).into_owned();
-                let title = title.map(|x| String::from_utf8_lossy(x).into_owned());
-                let site_url = site_url.map(|x| String::from_utf8_lossy(x).into_owned());
-
-                info!(
-                    "importing feed {} (\"{:?}\", site: {:?})",
-                    rss_url, title, site_url
-                );
-                feeds
-                    .as_mut()
-                    .unwrap()
-                    .push(ImportedFeed::new(rss_url, title, site_url));
             }
             Ok(Event::Eof) => break,
             Err(e) => {
@@ -205,11 +206,241 @@ fn from_reader<B: BufRead>(mut reader: Reader<B>) -> ImportResult {
         }
     }
 
-    // no group catch
-    if feeds.as_ref().map(|x| !x.is_empty()).unwrap_or(false) {
-        info!("processed group: {:?}", group.as_ref());
-        result.push((group.take(), feeds.take().unwrap()));
+    Ok(feeds)
+}
+
+/// Adds the feed described by `attrs`, found within `outlines`, or merges it into the feed with
+/// the same URL found earlier.
+fn add_feed(feeds: &mut Vec<ImportedFeed>, attrs: &Attributes, outlines: &[Outline]) {
+    let rss_url = attribute(attrs, b"xmlUrl").unwrap_or_default();
+    let title = attribute(attrs, b"title").or_else(|| attribute(attrs, b"text"));
+    let site_url = attribute(attrs, b"htmlUrl");
+
+    let folder = outlines
+        .iter()
+        .filter_map(|outline| match outline {
+            Outline::Folder(name) => name.clone(),
+            Outline::Feed => None,
+        })
+        .collect::<Vec<_>>();
+    // categories are comma separated, slash delimited paths such as `/Tech/Rust`
+    let categories = attribute(attrs, b"category").unwrap_or_default();
+    let groups = Some(folder)
+        .into_iter()
+        .chain(categories.split(',').map(|category| {
+            category
+                .split('/')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_owned)
+                .collect()
+        }))
+        .filter(|path: &Vec<String>| !path.is_empty());
+
+    info!(
+        "importing feed {} (\"{:?}\", site: {:?})",
+        rss_url, title, site_url
+    );
+    let feed = match feeds.iter().position(|feed| feed.rss_url == rss_url) {
+        Some(index) => {
+            let feed = &mut feeds[index];
+            feed.title = feed.title.take().or(title);
+            feed.site_url = feed.site_url.take().or(site_url);
+            feed
+        }
+        None => {
+            feeds.push(ImportedFeed::new(rss_url, title, site_url));
+            feeds.last_mut().unwrap()
+        }
+    };
+    for group in groups {
+        if !feed.groups.contains(&group) {
+            feed.groups.push(group);
+        }
+    }
+}
+
+/// Writes an OPML 2.0 document. Each group becomes a parent outline of its feeds followed by its
+/// subgroups, and `sparks` (feeds which belong to no group) are listed at the top level.
+pub fn to_writer<W: Write>(
+    writer: W,
+    groups: &[(Group, Vec<Feed>)],
+    sparks: &[Feed],
+) -> Result<()> {
+    let mut writer = Writer::new_with_indent(writer, b' ', 4);
+
+    writer.write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
+    writer.write_event(Event::Start(
+        BytesStart::borrowed_name(b"opml").with_attributes(vec![("version", "2.0")]),
+    ))?;
+    writer.write_event(Event::Start(BytesStart::borrowed_name(b"head")))?;
+    write_text_element(&mut writer, b"title", "lares subscriptions")?;
+    write_text_element(&mut writer, b"dateCreated", &Utc::now().to_rfc2822())?;
+    writer.write_event(Event::End(BytesEnd::borrowed(b"head")))?;
+    writer.write_event(Event::Start(BytesStart::borrowed_name(b"body")))?;
+
+    for entry in groups.iter() {
+        let (group, _) = entry;
+        let is_nested = groups
+            .iter()
+            .any(|(parent, _)| Some(parent.id) == group.parent_id);
+        if !is_nested && has_feeds(groups, entry) {
+            write_group(&mut writer, groups, entry)?;
+        }
+    }
+    // sparks come last, the importer would otherwise add them to the group that follows
+    for feed in sparks.iter() {
+        write_feed(&mut writer, feed)?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::borrowed(b"body")))?;
+    writer.write_event(Event::End(BytesEnd::borrowed(b"opml")))?;
+    writer.write(b"\n")?;
+    Ok(())
+}
+
+/// Writes `group` along with its subgroups found in `groups`.
+fn write_group<W: Write>(
+    writer: &mut Writer<W>,
+    groups: &[(Group, Vec<Feed>)],
+    (group, feeds): &(Group, Vec<Feed>),
+) -> Result<()> {
+    writer.write_event(Event::Start(
+        BytesStart::borrowed_name(b"outline").with_attributes(vec![
+            ("text", group.title.as_str()),
+            ("title", group.title.as_str()),
+        ]),
+    ))?;
+    for feed in feeds.iter() {
+        write_feed(writer, feed)?;
+    }
+    for subgroup in subgroups(groups, group) {
+        write_group(writer, groups, subgroup)?;
+    }
+    writer.write_event(Event::End(BytesEnd::borrowed(b"outline")))?;
+    Ok(())
+}
+
+/// Subgroups of `group` which have feeds themselves or in their own subgroups. The importer
+/// takes an outline without children for a feed, so empty groups are left out.
+fn subgroups<'a>(
+    groups: &'a [(Group, Vec<Feed>)],
+    group: &'a Group,
+) -> impl Iterator<Item = &'a (Group, Vec<Feed>)> {
+    groups
+        .iter()
+        .filter(move |(subgroup, _)| subgroup.parent_id == Some(group.id))
+        .filter(move |entry| has_feeds(groups, entry))
+}
+
+fn has_feeds(groups: &[(Group, Vec<Feed>)], (group, feeds): &(Group, Vec<Feed>)) -> bool {
+    !feeds.is_empty() || subgroups(groups, group).next().is_some()
+}
+
+fn write_text_element<W: Write>(writer: &mut Writer<W>, name: &[u8], text: &str) -> Result<()> {
+    writer.write_event(Event::Start(BytesStart::borrowed_name(name)))?;
+    writer.write_event(Event::Text(BytesText::from_plain_str(text)))?;
+    writer.write_event(Event::End(BytesEnd::borrowed(name)))?;
+    Ok(())
+}
+
+fn write_feed<W: Write>(writer: &mut Writer<W>, feed: &Feed) -> Result<()> {
+    let mut outline = BytesStart::borrowed_name(b"outline").with_attributes(vec![
+        ("type", "rss"),
+        ("text", feed.title.as_str()),
+        ("title", feed.title.as_str()),
+        ("xmlUrl", feed.url.as_str()),
+    ]);
+    // an empty `htmlUrl` would not pass validation when imported again
+    if !feed.site_url.is_empty() {
+        outline.push_attribute(("htmlUrl", feed.site_url.as_str()));
+    }
+    writer.write_event(Event::Empty(outline))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    type Summary = Vec<(String, String, String, Vec<Vec<String>>)>;
+
+    fn summary(result: ImportResult) -> Summary {
+        result
+            .unwrap()
+            .into_iter()
+            .map(|feed| {
+                (
+                    feed.rss_url,
+                    feed.title.unwrap_or_default(),
+                    feed.site_url.unwrap_or_default(),
+                    feed.groups,
+                )
+            })
+            .collect()
     }
 
-    Ok(result)
+    #[test]
+    fn test_export_import() {
+        let feed = |n: u32, title: &str| {
+            Feed::new(
+                title.to_owned(),
+                format!("https://example.com/feed{}?format=rss&lang=en", n),
+                format!("https://example.com/site{}", n),
+            )
+        };
+        let group = |id: u32, title: &str, parent_id: Option<u32>| Group {
+            id,
+            title: title.to_owned(),
+            parent_id,
+        };
+        let groups = vec![
+            (
+                group(1, "News & Blogs", None),
+                vec![feed(1, "Feed <1>"), feed(2, "Feed \"2\"")],
+            ),
+            (group(2, "Empty", None), vec![]),
+            (group(3, "Rust", None), vec![feed(2, "Feed \"2\"")]),
+            (group(4, "Compiler", Some(3)), vec![feed(5, "Feed 5")]),
+            (group(5, "Still Empty", Some(2)), vec![]),
+        ];
+        let sparks = vec![
+            feed(3, "Feed 3"),
+            Feed::new(
+                String::new(),
+                "https://example.com/feed4".to_owned(),
+                String::new(),
+            ),
+        ];
+
+        let mut opml = Vec::new();
+        to_writer(&mut opml, &groups, &sparks).unwrap();
+
+        let expected = |n: u32, title: &str, groups: &[&[&str]]| {
+            (
+                format!("https://example.com/feed{}?format=rss&lang=en", n),
+                title.to_owned(),
+                format!("https://example.com/site{}", n),
+                groups
+                    .iter()
+                    .map(|path| path.iter().map(|group| group.to_string()).collect())
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            summary(from_reader(Reader::from_reader(&opml[..]))),
+            vec![
+                expected(1, "Feed <1>", &[&["News & Blogs"]]),
+                expected(2, "Feed \"2\"", &[&["News & Blogs"], &["Rust"]]),
+                expected(5, "Feed 5", &[&["Rust", "Compiler"]]),
+                expected(3, "Feed 3", &[]),
+                (
+                    "https://example.com/feed4".to_owned(),
+                    String::new(),
+                    String::new(),
+                    vec![]
+                ),
+            ]
+        );
+    }
 }
@@ -1,7 +1,7 @@
 use either::Either;
 
-use crate::error::Result;
-use crate::find::find_rel_alternates;
+use crate::error::{Error, Result};
+use crate::find::{find_rel_alternates, find_rel_icons};
 
 pub struct RemoteFeed {
     url: String,
@@ -49,3 +49,74 @@ impl RemoteFeed {
         &self.url
     }
 }
+
+/// Fetches the icon of the given site and encodes it as a `data:` URI. Icons declared with
+/// `<link rel="icon" />` are preferred over `/favicon.ico`.
+pub async fn fetch_favicon(site_url: &str) -> Result<String> {
+    let base = url::Url::parse(site_url)?;
+
+    let mut candidates = match surf::get(site_url).await {
+        Ok(mut response) => {
+            let page = response.body_bytes().await?;
+            find_rel_icons(&page[..])
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|href| base.join(&href).ok())
+                .collect()
+        }
+        Err(_) => Vec::new(),
+    };
+    candidates.push(base.join("/favicon.ico")?);
+
+    for url in candidates {
+        if let Ok(Some(data)) = fetch_data_uri(&url).await {
+            return Ok(data);
+        }
+    }
+
+    Err(Error::message(format!(
+        "unable to find favicon for {}",
+        site_url
+    )))
+}
+
+async fn fetch_data_uri(url: &url::Url) -> Result<Option<String>> {
+    let mut response = surf::get(url.as_str()).await?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+
+    let mime = response
+        .header("Content-Type")
+        .and_then(|value| value.split(';').next())
+        .map(|mime| mime.trim().to_owned())
+        .filter(|mime| mime.starts_with("image/"))
+        .or_else(|| guess_image_mime(url.path()).map(ToOwned::to_owned));
+    let mime = match mime {
+        Some(mime) => mime,
+        None => return Ok(None),
+    };
+
+    let bytes = response.body_bytes().await?;
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(format!(
+        "data:{};base64,{}",
+        mime,
+        base64::encode(&bytes)
+    )))
+}
+
+fn guess_image_mime(path: &str) -> Option<&'static str> {
+    let extension = path.rsplit('.').next()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "ico" => Some("image/x-icon"),
+        "png" => Some("image/png"),
+        "gif" => Some("image/gif"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
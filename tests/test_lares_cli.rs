@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use assert_cmd::Command;
 use async_std::task::{self, JoinHandle};
 use chrono::TimeZone;
@@ -23,6 +23,8 @@ fn rand_string() -> String {
         .collect()
 }
 
+const FIXTURE_ETAG: &str = "\"rust-blog\"";
+
 fn get_fixtures_dir() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures")
 }
@@ -35,7 +37,7 @@ struct Lares {
 impl Lares {
     fn new() -> Result<Self> {
         let db = tempfile::Builder::new().suffix(".db").tempfile()?;
-        let pool = lares::model::get_pool(db.path())?;
+        let pool = lares::model::get_pool(db.path(), Default::default())?;
         Ok(Self { db, pool })
     }
 
@@ -51,6 +53,19 @@ impl Lares {
 
         let mut app = tide::new();
         app.at("/").serve_dir(get_fixtures_dir())?;
+        app.at("/cached/rust.xml")
+            .get(|request: tide::Request<()>| async move {
+                let etag = request.header("If-None-Match").map(|v| v.last().as_str());
+                if etag == Some(FIXTURE_ETAG) {
+                    return Ok(tide::Response::new(304));
+                }
+
+                let mut response = tide::Response::new(200);
+                response
+                    .set_body(tide::Body::from_file(get_fixtures_dir().join("rust.xml")).await?);
+                response.insert_header("ETag", FIXTURE_ETAG);
+                Ok(response)
+            });
 
         let web = task::spawn({
             let addr = addr.clone();
@@ -61,6 +76,69 @@ impl Lares {
 
         Ok((format!("http://{}", addr), web))
     }
+
+    /// Seeds the database with a feed containing `count` items.
+    fn seed_items(&self, count: u32) -> Result<lares::model::Feed> {
+        let conn = self.pool.get()?;
+        let feed = lares::model::Feed::new(
+            "Seeded Feed".to_owned(),
+            "http://example.com/feed".to_owned(),
+            "http://example.com/".to_owned(),
+        )
+        .insert(&conn)?;
+
+        let items = (0..count)
+            .map(|i| lares::model::Item {
+                id: 0,
+                feed_id: feed.id,
+                title: format!("Item {}", i),
+                author: "Author".to_owned(),
+                html: format!("<p>Item {}</p>", i),
+                url: format!("http://example.com/{}", i),
+                is_saved: false,
+                is_read: false,
+                created_on_time: chrono::Utc.timestamp(1_600_000_000 + i as i64, 0),
+                read_on_time: None,
+                guid: format!("item-{}", i),
+                date_source: lares::model::DateSource::Published,
+                updated_on_time: None,
+                content_hash: String::new(),
+            })
+            .collect();
+        lares::model::Item::insert_multi(&conn, items)?;
+
+        Ok(feed)
+    }
+
+    /// Sends a Fever API request and returns the decoded response.
+    fn fever(&self, query: &str, form: &str) -> Result<serde_json::Value> {
+        let app = lares::api::make_app(lares::state::State::new(self.pool.clone()));
+        let url = tide::http::Url::parse(&format!("http://localhost/?api&{}", query))?;
+        let mut request = tide::http::Request::new(tide::http::Method::Post, url);
+        request.set_body(form);
+        request.set_content_type(tide::http::mime::FORM);
+
+        task::block_on(async {
+            let mut response: tide::http::Response = app
+                .respond(request)
+                .await
+                .map_err(|e| anyhow!(e.to_string()))?;
+            let body = response
+                .body_string()
+                .await
+                .map_err(|e| anyhow!(e.to_string()))?;
+            Ok(serde_json::from_str(&body)?)
+        })
+    }
+}
+
+fn item_ids(response: &serde_json::Value) -> Vec<u64> {
+    response["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["id"].as_u64().unwrap())
+        .collect()
 }
 
 #[test]
@@ -156,6 +234,158 @@ fn test_crawl() -> Result<()> {
         chrono::Utc.ymd(2020, 08, 03).and_hms(0, 0, 0)
     );
 
+    // items go away with their feed
+    lares.cmd()?.args(&["feed", "delete", "1"]).unwrap();
+    assert!(lares::model::Item::all(&conn)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_feed_group_edit() -> Result<()> {
+    let lares = Lares::new()?;
+    let feed = lares.seed_items(1)?;
+    let id = feed.id.to_string();
+    let conn = lares.pool.get()?;
+    lares::model::Feed::new(
+        "Other".to_owned(),
+        "http://example.com/other".to_owned(),
+        "http://example.com/".to_owned(),
+    )
+    .insert(&conn)?;
+
+    let result = lares
+        .cmd()?
+        .args(&["feed", "edit", &id, "--title", "Renamed"])
+        .args(&["--site-url", "https://example.org/"])
+        .unwrap();
+    assert!(String::from_utf8(result.stdout)?.contains("Name: Renamed"));
+    let feed = lares::model::Feed::get(&conn, feed.id)?;
+    assert_eq!(feed.title, "Renamed");
+    assert_eq!(feed.site_url, "https://example.org/");
+    assert_eq!(feed.url, "http://example.com/feed");
+
+    lares.cmd()?.args(&["feed", "edit", &id]).assert().failure();
+    lares
+        .cmd()?
+        .args(&["feed", "edit", &id, "--url", "not a url"])
+        .assert()
+        .failure();
+    lares
+        .cmd()?
+        .args(&["feed", "edit", &id, "--url", "http://example.com/other"])
+        .assert()
+        .failure();
+    lares
+        .cmd()?
+        .args(&["feed", "edit", &id, "--url", "http://example.com/moved"])
+        .unwrap();
+    assert_eq!(
+        lares::model::Feed::get(&conn, feed.id)?.url,
+        "http://example.com/moved"
+    );
+
+    lares.cmd()?.args(&["group", "add", "Before"]).unwrap();
+    lares
+        .cmd()?
+        .args(&["group", "add-feed", &id, "Before"])
+        .unwrap();
+    assert!(!lares::model::Feed::get(&conn, feed.id)?.is_spark);
+    lares
+        .cmd()?
+        .args(&["group", "rename", "Before", "After"])
+        .unwrap();
+    lares.cmd()?.args(&["group", "add", "Taken"]).unwrap();
+    lares
+        .cmd()?
+        .args(&["group", "rename", "After", "Taken"])
+        .assert()
+        .failure();
+
+    let result = lares
+        .cmd()?
+        .args(&["group", "remove-feed", &id, "After"])
+        .unwrap();
+    assert!(String::from_utf8(result.stdout)?.contains("removed from group After"));
+    assert!(lares::model::Feed::get(&conn, feed.id)?.is_spark);
+    lares
+        .cmd()?
+        .args(&["group", "remove-feed", &id, "After"])
+        .assert()
+        .failure();
+    assert_eq!(lares::model::Feed::count(&conn)?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_group_tree() -> Result<()> {
+    let lares = Lares::new()?;
+    let feed = lares.seed_items(3)?;
+
+    lares.cmd()?.args(&["group", "add", "Parent"]).unwrap();
+    lares
+        .cmd()?
+        .args(&["group", "add", "Child", "--parent", "Parent"])
+        .unwrap();
+    lares.cmd()?.args(&["group", "add", "Other"]).unwrap();
+    lares
+        .cmd()?
+        .args(&["group", "add-feed", &feed.id.to_string(), "Child"])
+        .unwrap();
+    lares
+        .cmd()?
+        .args(&["group", "add", "Orphan", "--parent", "Missing"])
+        .assert()
+        .failure();
+
+    let result = lares
+        .cmd()?
+        .args(&["group", "move", "Parent", "--parent", "Child"])
+        .assert()
+        .failure();
+    let stderr = String::from_utf8(result.get_output().stderr.clone())?;
+    assert!(stderr.contains("into its own subgroup"));
+
+    let result = lares
+        .cmd()?
+        .args(&["group", "move", "Child", "--parent", "Other"])
+        .unwrap();
+    assert!(String::from_utf8(result.stdout)?.contains("Group 'Child' moved into 'Other'."));
+    lares
+        .cmd()?
+        .args(&["group", "move", "Child", "-p", "Parent"])
+        .unwrap();
+
+    let response = lares.fever("groups", "")?;
+    let titles = response["groups"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|group| group["title"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, vec!["Parent", "Parent / Child", "Other"]);
+    let feed_ids = response["feeds_groups"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|group| {
+            (
+                group["group_id"].as_u64().unwrap(),
+                group["feed_ids"].as_str().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(feed_ids, vec![(1, "1"), (2, "1")]);
+
+    lares.fever("", "mark=group&as=read&id=1")?;
+    let conn = lares.pool.get()?;
+    assert!(lares::model::Item::unread(&conn)?.is_empty());
+
+    lares.cmd()?.args(&["group", "move", "Child"]).unwrap();
+    let response = lares.fever("groups", "")?;
+    assert_eq!(response["groups"][1]["title"], "Child");
+
     Ok(())
 }
 
@@ -181,6 +411,323 @@ fn test_import() -> Result<()> {
     Ok(())
 }
 
+/// Paths of the groups, e.g. `Tech / Rust`, along with `feed_key` of their feeds.
+fn group_summary(
+    lares: &Lares,
+    feed_key: fn(lares::model::Feed) -> String,
+) -> Result<Vec<(String, Vec<String>)>> {
+    let conn = lares.pool.get()?;
+    let mut summary = Vec::new();
+    for group in lares::model::Group::all_with_paths(&conn)? {
+        let feeds = group.get_feeds(&conn)?;
+        summary.push((group.title, feeds.into_iter().map(feed_key).collect()));
+    }
+    Ok(summary)
+}
+
+/// Cells of the rows of tables printed in the `FORMAT_NO_BORDER_LINE_SEPARATOR` format.
+fn table_rows(stdout: &str) -> Vec<Vec<String>> {
+    stdout
+        .lines()
+        .filter(|line| line.contains('|'))
+        .map(|line| line.split('|').map(|cell| cell.trim().to_owned()).collect())
+        .collect()
+}
+
+fn to_cells(cells: &[&str]) -> Vec<String> {
+    cells.iter().map(|cell| cell.to_string()).collect()
+}
+
+fn to_strings(groups: &[(&str, &[&str])]) -> Vec<(String, Vec<String>)> {
+    groups
+        .iter()
+        .map(|(group, feeds)| {
+            (
+                group.to_string(),
+                feeds.iter().map(|feed| feed.to_string()).collect(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_import_nested() -> Result<()> {
+    let lares = Lares::new()?;
+    let opml = get_fixtures_dir().join("nested.opml");
+    lares.cmd()?.args(&["feed", "import"]).arg(&opml).unwrap();
+
+    assert_eq!(
+        group_summary(&lares, |feed| feed.title)?,
+        to_strings(&[
+            ("Tech", &["Feed 1 Title", "Feed 4 Title"]),
+            ("Tech / Rust", &["Feed 3 Title"]),
+            ("Tech / Rust / Compiler", &["Feed 2 Title"]),
+        ])
+    );
+
+    let conn = lares.pool.get()?;
+    let feeds = lares::model::Feed::all(&conn)?;
+    assert_eq!(feeds.len(), 5);
+    assert!(feeds[4].is_spark);
+    Ok(())
+}
+
+#[test]
+fn test_import_same_name() -> Result<()> {
+    let lares = Lares::new()?;
+    let opml = get_fixtures_dir().join("same-name.opml");
+    let result = lares
+        .cmd()?
+        .args(&["feed", "import", "--dry-run"])
+        .arg(&opml)
+        .unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(stdout.contains("Groups to create: Tech, Tech / News, Sports, Sports / News\n"));
+
+    let result = lares.cmd()?.args(&["feed", "import"]).arg(&opml).unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(table_rows(&stdout).contains(&to_cells(&["2", "0", "0", "0", "4"])));
+    assert_eq!(
+        group_summary(&lares, |feed| feed.title)?,
+        to_strings(&[
+            ("Tech", &[]),
+            ("Tech / News", &["Feed 1 Title"]),
+            ("Sports", &[]),
+            ("Sports / News", &["Feed 2 Title"]),
+        ])
+    );
+
+    // a shared title is ambiguous, unlike the path of the group
+    lares
+        .cmd()?
+        .args(&["group", "add-feed", "1", "News"])
+        .assert()
+        .failure();
+    lares
+        .cmd()?
+        .args(&["group", "add-feed", "1", "Sports / News"])
+        .unwrap();
+    lares
+        .cmd()?
+        .args(&["group", "add", "News", "--parent", "Tech"])
+        .assert()
+        .failure();
+    assert_eq!(
+        group_summary(&lares, |feed| feed.title)?[3],
+        to_strings(&[("Sports / News", &["Feed 1 Title", "Feed 2 Title"])])[0]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_import_multi_group() -> Result<()> {
+    let lares = Lares::new()?;
+    let opml = get_fixtures_dir().join("multi-group.opml");
+    lares.cmd()?.args(&["feed", "import"]).arg(&opml).unwrap();
+
+    assert_eq!(
+        group_summary(&lares, |feed| feed.title)?,
+        to_strings(&[
+            ("News", &["Feed 1 Title", "Feed 2 Title"]),
+            ("Favorites", &["Feed 1 Title", "Feed 3 Title"]),
+        ])
+    );
+
+    let conn = lares.pool.get()?;
+    assert_eq!(lares::model::Feed::all(&conn)?.len(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_import_category() -> Result<()> {
+    let lares = Lares::new()?;
+    let opml = get_fixtures_dir().join("category.opml");
+    lares.cmd()?.args(&["feed", "import"]).arg(&opml).unwrap();
+
+    assert_eq!(
+        group_summary(&lares, |feed| feed.title)?,
+        to_strings(&[
+            ("Tech", &[]),
+            ("Tech / Rust", &["Feed 1 Title", "Feed 2 Title"]),
+            ("Favorites", &["Feed 1 Title", "Feed 3 Title"]),
+            ("News", &["Feed 3 Title"]),
+        ])
+    );
+
+    let conn = lares.pool.get()?;
+    let feeds = lares::model::Feed::all(&conn)?;
+    assert_eq!(feeds.len(), 4);
+    assert!(feeds[3].is_spark);
+    Ok(())
+}
+
+#[test]
+fn test_group_delete() -> Result<()> {
+    let lares = Lares::new()?;
+    let opml = get_fixtures_dir().join("normal.opml");
+    lares.cmd()?.args(&["feed", "import"]).arg(&opml).unwrap();
+
+    let conn = lares.pool.get()?;
+    let group = lares::model::Group::get_by_name(&conn, "Group 1 Title")?;
+    let feed_ids = lares::model::FeedGroup::get_by_group(&conn, group.id)?.feed_ids;
+    assert!(!feed_ids.is_empty());
+
+    // feeds left without a group are shown as sparks
+    lares
+        .cmd()?
+        .args(&["group", "delete", "Group 1 Title"])
+        .unwrap();
+    for id in feed_ids {
+        assert!(lares::model::Feed::get(&conn, id)?.is_spark);
+    }
+    assert!(lares::model::FeedGroup::get_by_group(&conn, group.id).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_import_dry_run() -> Result<()> {
+    let lares = Lares::new()?;
+    let has_row = |stdout: &str, status: &str, url: &str| {
+        table_rows(stdout)
+            .iter()
+            .any(|row| row[0] == status && row[1] == url)
+    };
+    // added, already existing, invalid and failed feeds, and created groups
+    let has_summary =
+        |stdout: &str, counts: &[&str]| table_rows(stdout).contains(&to_cells(counts));
+
+    let normal = get_fixtures_dir().join("normal.opml");
+    let result = lares
+        .cmd()?
+        .args(&["feed", "import", "--dry-run"])
+        .arg(&normal)
+        .unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(has_row(&stdout, "new", "https://example.com/feed1"));
+    assert!(has_row(&stdout, "new", "https://example.com/feed4"));
+    assert!(stdout.contains("Groups to create: Group 1 Title, Group 2 Text"));
+    let conn = lares.pool.get()?;
+    assert!(lares::model::Feed::all(&conn)?.is_empty());
+    assert!(lares::model::Group::all(&conn)?.is_empty());
+
+    let result = lares.cmd()?.args(&["feed", "import"]).arg(&normal).unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(has_row(&stdout, "added", "https://example.com/feed1"));
+    assert!(has_summary(&stdout, &["4", "0", "0", "0", "2"]));
+
+    let mut opml = tempfile::NamedTempFile::new()?;
+    opml.as_file_mut().write_all(
+        br#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+    <body>
+        <outline text="Group 1 Title">
+            <outline xmlUrl="https://example.com/feed1" title="Feed 1 Title" htmlUrl="http://example.com/site1" />
+            <outline xmlUrl="https://example.com/feed5" title="Feed 5 Title" htmlUrl="http://example.com/site5" />
+        </outline>
+        <outline text="Group 3">
+            <outline xmlUrl="https://example.com/feed6" title="Feed 6 Title" htmlUrl="http://example.com/site6" />
+            <outline xmlUrl="not a url" title="Invalid" />
+        </outline>
+    </body>
+</opml>"#,
+    )?;
+    opml.as_file_mut().flush()?;
+
+    let result = lares
+        .cmd()?
+        .args(&["feed", "import", "--dry-run"])
+        .arg(opml.path())
+        .unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(has_row(&stdout, "exists", "https://example.com/feed1"));
+    assert!(has_row(&stdout, "new", "https://example.com/feed5"));
+    assert!(has_row(&stdout, "invalid", "not a url"));
+    assert!(stdout.contains("Groups to create: Group 3\n"));
+    assert_eq!(lares::model::Feed::all(&conn)?.len(), 4);
+
+    let result = lares
+        .cmd()?
+        .args(&["feed", "import"])
+        .arg(opml.path())
+        .unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(has_summary(&stdout, &["2", "1", "1", "0", "1"]));
+    assert_eq!(lares::model::Feed::all(&conn)?.len(), 6);
+    assert_eq!(
+        group_summary(&lares, |feed| feed.title)?,
+        to_strings(&[
+            (
+                "Group 1 Title",
+                &["Feed 1 Title", "Feed 2 Text", "Feed 5 Title"]
+            ),
+            ("Group 2 Text", &["Feed 3 Title", "Feed 4 Text"]),
+            ("Group 3", &["Feed 6 Title"]),
+        ])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_export() -> Result<()> {
+    let lares = Lares::new()?;
+    let opml = get_fixtures_dir().join("normal.opml");
+    lares.cmd()?.args(&["feed", "import"]).arg(&opml).unwrap();
+    lares::model::Feed::new(
+        "Spark".to_owned(),
+        "https://example.com/spark".to_owned(),
+        "https://example.com/".to_owned(),
+    )
+    .insert(&*lares.pool.get()?)?;
+    lares
+        .cmd()?
+        .args(&["group", "add", "Nested", "--parent", "Group 1 Title"])
+        .unwrap();
+    lares
+        .cmd()?
+        .args(&["group", "add-feed", "4", "Nested"])
+        .unwrap();
+
+    let result = lares.cmd()?.args(&["feed", "export"]).unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(stdout.contains(r#"<outline text="Group 1 Title" title="Group 1 Title">"#));
+    assert!(stdout
+        .contains(r#"xmlUrl="https://example.com/feed1" htmlUrl="http://example.com/site1"/>"#));
+
+    let exported = tempfile::NamedTempFile::new()?;
+    lares
+        .cmd()?
+        .args(&["feed", "export"])
+        .arg(exported.path())
+        .unwrap();
+    let imported = Lares::new()?;
+    imported
+        .cmd()?
+        .args(&["feed", "import"])
+        .arg(exported.path())
+        .unwrap();
+
+    // groups and feeds are created in the order of the document, so their ids may differ
+    let sorted_summary = |lares: &Lares| -> Result<_> {
+        let mut summary = group_summary(lares, |feed| feed.url)?;
+        for (_, feeds) in summary.iter_mut() {
+            feeds.sort();
+        }
+        summary.sort();
+        Ok(summary)
+    };
+    let summary = sorted_summary(&imported)?;
+    assert_eq!(summary, sorted_summary(&lares)?);
+    assert_eq!(summary[1].0, "Group 1 Title / Nested");
+    let conn = imported.pool.get()?;
+    let feeds = lares::model::Feed::all(&conn)?;
+    assert_eq!(feeds.len(), 5);
+    assert_eq!(feeds[4].title, "Spark");
+
+    Ok(())
+}
+
 #[test]
 fn test_import_fill_missing() -> Result<()> {
     let lares = Lares::new()?;
@@ -223,3 +770,705 @@ fn test_import_flat() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_favicon() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let feed = {
+        let conn = lares.pool.get()?;
+        lares::model::Feed::new(
+            "Test Site".to_owned(),
+            format!("{}/rust.xml", addr),
+            format!("{}/site.html", addr),
+        )
+        .insert(&conn)?
+    };
+    assert_eq!(feed.favicon_id, 0);
+
+    let state = lares::state::State::new(lares.pool.clone());
+    let feed = task::block_on(feed.update_favicon(state))?;
+
+    let conn = lares.pool.get()?;
+    let favicons = lares::model::Favicon::all(&conn)?;
+    assert_eq!(favicons.len(), 1);
+    assert_eq!(feed.favicon_id, favicons[0].id);
+    assert_eq!(
+        lares::model::Feed::get(&conn, feed.id)?.favicon_id,
+        feed.favicon_id
+    );
+
+    let icon = std::fs::read(get_fixtures_dir().join("icon.png"))?;
+    assert_eq!(
+        favicons[0].data,
+        format!("data:image/png;base64,{}", base64::encode(&icon))
+    );
+    assert!(!feed.needs_favicon(chrono::Utc::now()));
+
+    let feed = lares::model::Feed::new(
+        "No Icon".to_owned(),
+        format!("{}/nodate.xml", addr),
+        format!("{}/rust.xml", addr),
+    )
+    .insert(&conn)?;
+    assert!(feed.needs_favicon(chrono::Utc::now()));
+
+    let state = lares::state::State::new(lares.pool.clone());
+    let id = feed.id;
+    assert!(task::block_on(feed.update_favicon(state)).is_err());
+    let feed = lares::model::Feed::get(&conn, id)?;
+    assert_eq!(feed.favicon_id, 0);
+    assert!(feed.favicon_checked.is_some());
+    assert!(!feed.needs_favicon(chrono::Utc::now()));
+    assert!(feed.needs_favicon(chrono::Utc::now() + chrono::Duration::days(30)));
+
+    Ok(())
+}
+
+#[test]
+fn test_items() -> Result<()> {
+    let lares = Lares::new()?;
+    lares.seed_items(120)?;
+
+    let response = lares.fever("items", "")?;
+    assert_eq!(response["total_items"], 120);
+    assert_eq!(item_ids(&response), (1..=50).collect::<Vec<_>>());
+
+    let response = lares.fever("items&since_id=100", "")?;
+    assert_eq!(item_ids(&response), (101..=120).collect::<Vec<_>>());
+
+    let response = lares.fever("items&max_id=60", "")?;
+    assert_eq!(item_ids(&response), (10..=59).rev().collect::<Vec<_>>());
+
+    let response = lares.fever("items&max_id=3", "")?;
+    assert_eq!(item_ids(&response), vec![2, 1]);
+
+    let response = lares.fever("items&with_ids=7,3,200,110", "")?;
+    assert_eq!(item_ids(&response), vec![3, 7, 110]);
+
+    let ids = (1..=60)
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let response = lares.fever(&format!("items&with_ids={}", ids), "")?;
+    assert_eq!(item_ids(&response).len(), 50);
+
+    Ok(())
+}
+
+#[test]
+fn test_unread_recently_read() -> Result<()> {
+    let lares = Lares::new()?;
+    lares.seed_items(10)?;
+
+    let unread = |lares: &Lares| -> Result<String> {
+        let response = lares.fever("unread_item_ids", "")?;
+        Ok(response["unread_item_ids"].as_str().unwrap().to_owned())
+    };
+
+    lares.fever("", "mark=item&as=read&id=2")?;
+    lares.fever("", "mark=group&as=read&id=0&before=1600000004")?;
+    assert_eq!(unread(&lares)?, "6,7,8,9,10");
+
+    lares.fever("", "unread_recently_read=1")?;
+    assert_eq!(unread(&lares)?, "1,3,4,5,6,7,8,9,10");
+
+    lares.fever("", "mark=item&as=unread&id=2")?;
+    assert_eq!(unread(&lares)?, "1,2,3,4,5,6,7,8,9,10");
+
+    Ok(())
+}
+
+#[test]
+fn test_links() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let rust = format!("{}/rust.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &rust]).output()?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    // fixture items are from 2020, so the default window of 7 days is empty
+    let response = lares.fever("links", "")?;
+    assert!(response["links"].as_array().unwrap().is_empty());
+
+    let response = lares.fever("links&range=100000", "")?;
+    let links = response["links"].as_array().unwrap();
+    assert_eq!(links.len(), 50);
+    assert!(links.iter().all(|link| link["feed_id"] == 1));
+    assert!(links
+        .iter()
+        .any(|link| link["url"] == "https://www.rust-lang.org/install.html"));
+
+    // out of range windows and pages are answered rather than crashing the handler
+    let max = u32::MAX.to_string();
+    for query in &[
+        format!("links&offset={}", max),
+        format!("links&range={}", max),
+        format!("links&range=100000&page={}", max),
+    ] {
+        let response = lares.fever(query, "")?;
+        assert_eq!(response["auth"], 1);
+    }
+    let response = lares.fever(&format!("links&range={}", max), "")?;
+    assert_eq!(response["links"].as_array().unwrap().len(), 50);
+
+    Ok(())
+}
+
+#[test]
+fn test_crawl_ttl() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let ttl = format!("{}/ttl.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &ttl]).output()?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    let conn = lares.pool.get()?;
+    let feed = lares::model::Feed::get(&conn, 1)?;
+    assert_eq!(
+        feed.update_hint,
+        Some(lares::model::Feed::MAX_CRAWL_INTERVAL)
+    );
+
+    let minutes = u32::MAX.to_string();
+    lares
+        .cmd()?
+        .args(&["feed", "set-interval", "1", &minutes])
+        .unwrap();
+    let feed = lares::model::Feed::get(&conn, 1)?;
+    assert_eq!(feed.fetch_interval, Some(u32::MAX));
+
+    Ok(())
+}
+
+#[test]
+fn test_crawl_conditional() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let rust = format!("{}/cached/rust.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &rust]).output()?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    let conn = lares.pool.get()?;
+    let feed = lares::model::Feed::get(&conn, 1)?;
+    assert_eq!(feed.etag.as_deref(), Some(FIXTURE_ETAG));
+    assert_eq!(feed.last_status, Some(200));
+    assert_eq!(lares::model::Item::all(&conn)?.len(), 10);
+
+    // the body is not parsed again when unchanged, so removed items stay removed
+    conn.execute("DELETE FROM `item`", rusqlite::NO_PARAMS)?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    let feed = lares::model::Feed::get(&conn, 1)?;
+    assert_eq!(feed.etag.as_deref(), Some(FIXTURE_ETAG));
+    assert_eq!(feed.last_status, Some(304));
+    assert!(lares::model::Item::all(&conn)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_crawl_failure() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let rust = format!("{}/rust.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &rust]).output()?;
+
+    let conn = lares.pool.get()?;
+    conn.execute(
+        "UPDATE `feed` SET `url` = ?1",
+        &[format!("{}/missing.xml", addr)],
+    )?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap_err();
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap_err();
+
+    let feed = lares::model::Feed::get(&conn, 1)?;
+    assert_eq!(feed.error_count, 2);
+    assert_eq!(feed.last_status, Some(404));
+    assert!(feed.last_error.is_some());
+    assert!(feed.last_success.is_none());
+
+    let result = lares.cmd()?.args(&["feed", "status", "1"]).unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(stdout.contains("Consecutive errors: 2"));
+    assert!(stdout.contains("Last error: "));
+
+    conn.execute("UPDATE `feed` SET `url` = ?1", &[&rust])?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    let feed = lares::model::Feed::get(&conn, 1)?;
+    assert_eq!(feed.error_count, 0);
+    assert!(feed.last_error.is_none());
+    assert!(feed.last_success.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_crawl_atomic() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let rust = format!("{}/rust.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &rust]).output()?;
+
+    // fail the crawl after its items have been inserted
+    let conn = lares.pool.get()?;
+    conn.execute_batch(
+        r"
+    CREATE TRIGGER `fail_crawl` BEFORE UPDATE OF `last_updated` ON `feed`
+    BEGIN SELECT RAISE(ABORT, 'crawl failed'); END",
+    )?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap_err();
+    assert!(lares::model::Item::all(&conn)?.is_empty());
+
+    conn.execute_batch("DROP TRIGGER `fail_crawl`")?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+    assert_eq!(lares::model::Item::all(&conn)?.len(), 10);
+
+    Ok(())
+}
+
+#[test]
+fn test_db_purge() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let rust = format!("{}/rust.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &rust]).output()?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    lares.cmd()?.args(&["db", "purge"]).unwrap_err();
+
+    let result = lares
+        .cmd()?
+        .args(&["db", "purge", "--dry-run", "--max-items", "3"])
+        .unwrap();
+    assert!(String::from_utf8(result.stdout)?.contains("7 items would be purged"));
+
+    let conn = lares.pool.get()?;
+    assert_eq!(lares::model::Item::all(&conn)?.len(), 10);
+
+    let result = lares
+        .cmd()?
+        .args(&["db", "purge", "--max-items", "3"])
+        .unwrap();
+    assert!(String::from_utf8(result.stdout)?.contains("7 items purged"));
+    let items = lares::model::Item::all(&conn)?;
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[2].title, "Announcing Rust 1.45.2");
+
+    // purged entries are not stored again
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+    assert_eq!(lares::model::Item::all(&conn)?.len(), 3);
+
+    // and are remembered as long as the feed lists them, but not forever
+    let tombstones = || -> Result<u32> {
+        Ok(conn.query_row(
+            "SELECT COUNT(*) FROM `item_tombstone`",
+            rusqlite::NO_PARAMS,
+            |row| row.get(0),
+        )?)
+    };
+    let forget = || {
+        conn.execute(
+            "UPDATE `item_tombstone` SET `purged` = ?1",
+            &[chrono::Utc.timestamp(0, 0)],
+        )
+    };
+    forget()?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+    lares
+        .cmd()?
+        .args(&["db", "purge", "--max-items", "3"])
+        .unwrap();
+    assert_eq!(tombstones()?, 7);
+    forget()?;
+    lares
+        .cmd()?
+        .args(&["db", "purge", "--max-items", "3"])
+        .unwrap();
+    assert_eq!(tombstones()?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_item_search() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let rust = format!("{}/rust.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &rust]).output()?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    let result = lares
+        .cmd()?
+        .args(&["item", "search", "announcing rustup"])
+        .unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    // posts about rustup rank before release notes merely mentioning it
+    assert!(stdout
+        .lines()
+        .next()
+        .unwrap()
+        .contains("Announcing Rustup 1.22"));
+    assert!(stdout.contains("Announcing Rustup 1.22.1"));
+    assert!(stdout.contains("Announcing Rustup 1.22.0"));
+
+    let result = lares
+        .cmd()?
+        .args(&["item", "search", "rustup", "--feed", "2"])
+        .unwrap();
+    assert!(String::from_utf8(result.stdout)?.contains("No items found."));
+
+    let response = lares.fever("search&q=rustup", "")?;
+    let titles = response["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["title"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert!(titles.contains(&"Announcing Rustup 1.22.1"));
+
+    lares.fever("", "mark=feed&as=read&id=1")?;
+    let response = lares.fever("search&q=rustup&unread=1", "")?;
+    assert!(response["items"].as_array().unwrap().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_item_commands() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let rust = format!("{}/rust.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &rust]).output()?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    let conn = lares.pool.get()?;
+    let id = lares::model::Item::all(&conn)?
+        .into_iter()
+        .find(|item| item.title == "Announcing Rust 1.45.2")
+        .unwrap()
+        .id
+        .to_string();
+
+    let list = |args: &[&str]| -> Result<String> {
+        let result = lares.cmd()?.args(&["item", "list"]).args(args).unwrap();
+        Ok(String::from_utf8(result.stdout)?)
+    };
+    let stdout = list(&["--limit", "2"])?;
+    assert!(stdout.starts_with(&format!(
+        "{} [2020-08-03 00:00:00] Announcing Rust 1.45.2 (unread)",
+        id
+    )));
+    assert!(stdout.contains("Announcing Rust 1.45.1"));
+    assert!(!stdout.contains("Announcing Rust 1.45.0"));
+    assert!(list(&["--since", "2020-07-30"])?.contains("Announcing Rust 1.45.1"));
+    assert!(!list(&["--since", "2020-07-31"])?.contains("Announcing Rust 1.45.1"));
+    assert!(list(&["--saved"])?.contains("No items found."));
+
+    let result = lares.cmd()?.args(&["item", "read", &id]).unwrap();
+    assert!(String::from_utf8(result.stdout)?.contains(&format!("Item {} marked as read", id)));
+    lares.cmd()?.args(&["item", "save", &id]).unwrap();
+    assert!(!list(&["--unread"])?.contains("Announcing Rust 1.45.2"));
+    assert!(list(&["--saved"])?.contains("Announcing Rust 1.45.2 (saved)"));
+    lares.cmd()?.args(&["item", "unread", &id]).unwrap();
+    lares.cmd()?.args(&["item", "unsave", &id]).unwrap();
+    assert!(list(&["--unread", "--feed", "1"])?.contains("Announcing Rust 1.45.2 (unread)"));
+    assert!(list(&["--saved"])?.contains("No items found."));
+
+    let result = lares.cmd()?.args(&["item", "show", &id]).unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(stdout.contains("Title: Announcing Rust 1.45.2"));
+    assert!(stdout.contains("Feed: Rust Blog"));
+    assert!(stdout.contains("Author: The Rust Release Team"));
+    assert!(stdout.contains("Status: unread"));
+    assert!(stdout.contains("new version of Rust, 1.45.2. Rust is a programming language"));
+    assert!(stdout.contains("you can get rustup <https://www.rust-lang.org/install.html> from"));
+    assert!(stdout.contains("\nrustup update stable\n"));
+    assert!(!stdout.contains("<p>"));
+
+    lares
+        .cmd()?
+        .args(&["item", "show", "9999"])
+        .assert()
+        .failure();
+    lares.cmd()?.args(&["item", "read"]).assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_crawl_guid() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let guid = format!("{}/guid.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &guid]).output()?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    let conn = lares.pool.get()?;
+    let items = lares::model::Item::all(&conn)?;
+    assert_eq!(items.len(), 2);
+    assert!(items.iter().any(|item| item.guid == "guid-example-1"));
+
+    // same entries under different tracking parameters
+    conn.execute(
+        "UPDATE `feed` SET `url` = ?1",
+        &[format!("{}/guid-moved.xml", addr)],
+    )?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+    assert_eq!(lares::model::Item::all(&conn)?.len(), 2);
+
+    // items stored before guids were recorded are keyed by their url, and take the guid over
+    conn.execute("UPDATE `feed` SET `url` = ?1", &[&guid])?;
+    conn.execute(
+        "UPDATE `item` SET `guid` = `url`, `is_read` = 1 WHERE `guid` = 'guid-example-1'",
+        rusqlite::NO_PARAMS,
+    )?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+    let items = lares::model::Item::all(&conn)?;
+    assert_eq!(items.len(), 2);
+    assert!(items
+        .iter()
+        .any(|item| item.guid == "guid-example-1" && item.is_read));
+
+    // entries without guid nor link are identified by their content
+    let noguid = format!("{}/noguid.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &noguid]).output()?;
+    lares.cmd()?.args(&["feed", "crawl", "2"]).unwrap();
+    lares.cmd()?.args(&["feed", "crawl", "2"]).unwrap();
+
+    let feed = lares::model::Feed::get(&conn, 2)?;
+    let items = feed.items(&conn, None)?;
+    assert_eq!(items.len(), 2);
+    assert!(items.iter().all(|item| item.guid.starts_with("md5:")));
+    assert!(items.iter().any(|item| item.url.is_empty()));
+
+    Ok(())
+}
+
+#[test]
+fn test_crawl_dates() -> Result<()> {
+    use lares::model::DateSource;
+
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let updated = format!("{}/updated.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &updated]).output()?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    let conn = lares.pool.get()?;
+    let items = lares::model::Feed::get(&conn, 1)?.items(&conn, None)?;
+    assert_eq!(items.len(), 2);
+    let first = items
+        .iter()
+        .find(|item| item.title == "First post")
+        .unwrap();
+    assert_eq!(first.date_source, DateSource::Updated);
+    assert_eq!(
+        first.created_on_time,
+        chrono::Utc.ymd(2020, 6, 1).and_hms(10, 0, 0)
+    );
+    let second = items
+        .iter()
+        .find(|item| item.title == "Second post")
+        .unwrap();
+    assert_eq!(second.date_source, DateSource::Published);
+    assert_eq!(
+        second.created_on_time,
+        chrono::Utc.ymd(2020, 6, 2).and_hms(9, 0, 0)
+    );
+
+    let nodate = format!("{}/nodate.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &nodate]).output()?;
+    let before = chrono::Utc::now();
+    lares.cmd()?.args(&["feed", "crawl", "2"]).unwrap();
+
+    let items = lares::model::Feed::get(&conn, 2)?.items(&conn, None)?;
+    assert_eq!(items.len(), 2);
+    for item in items.iter() {
+        assert_eq!(item.date_source, DateSource::FirstSeen);
+        assert!(item.created_on_time >= before - chrono::Duration::seconds(1));
+    }
+
+    // the first-seen time sticks on later crawls
+    lares.cmd()?.args(&["feed", "crawl", "2"]).unwrap();
+    let again = lares::model::Feed::get(&conn, 2)?.items(&conn, None)?;
+    assert_eq!(again.len(), 2);
+    assert_eq!(again[0].created_on_time, items[0].created_on_time);
+
+    Ok(())
+}
+
+#[test]
+fn test_crawl_revision() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let updated = format!("{}/updated.xml", addr);
+    let _ = lares.cmd()?.args(&["feed", "add", &updated]).output()?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    let conn = lares.pool.get()?;
+    let second = lares::model::Item::all(&conn)?
+        .into_iter()
+        .find(|item| item.title == "Second post")
+        .unwrap();
+    let id = second.id.to_string();
+
+    let result = lares.cmd()?.args(&["item", "diff", &id]).unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(stdout.contains("has not changed"));
+
+    conn.execute(
+        "UPDATE `feed` SET `url` = ?1",
+        &[format!("{}/updated-revised.xml", addr)],
+    )?;
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+
+    assert_eq!(lares::model::Item::all(&conn)?.len(), 2);
+    let item = lares::model::Item::get(&conn, second.id)?;
+    assert!(item.html.contains("Corrected"));
+    let revisions = lares::model::ItemRevision::by_item(&conn, second.id)?;
+    assert_eq!(revisions.len(), 1);
+    assert_eq!(revisions[0].html, second.html);
+
+    let result = lares.cmd()?.args(&["item", "diff", &id]).unwrap();
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(stdout.contains("+ <p>Corrected</p>"));
+    assert!(stdout.contains("  <p>Second</p>"));
+
+    // unchanged entries are left alone on later crawls
+    lares.cmd()?.args(&["feed", "crawl", "1"]).unwrap();
+    assert_eq!(
+        lares::model::ItemRevision::by_item(&conn, second.id)?.len(),
+        1
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_db_migrate() -> Result<()> {
+    // every release's schema, along with some data, is dumped into `fixtures/db`
+    for entry in std::fs::read_dir(get_fixtures_dir().join("db"))? {
+        let dump = std::fs::read_to_string(entry?.path())?;
+        let db = tempfile::Builder::new().suffix(".db").tempfile()?;
+        rusqlite::Connection::open(db.path())?.execute_batch(&dump)?;
+
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.env("LARES_DATABASE", db.path());
+        let result = cmd.args(&["db", "status"]).unwrap();
+        assert!(String::from_utf8(result.stdout)?.contains("pending"));
+
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.env("LARES_DATABASE", db.path());
+        let result = cmd.args(&["db", "migrate"]).unwrap();
+        assert!(String::from_utf8(result.stdout)?.contains("Applied migration"));
+
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.env("LARES_DATABASE", db.path());
+        let result = cmd.args(&["db", "status"]).unwrap();
+        assert!(!String::from_utf8(result.stdout)?.contains("pending"));
+
+        let pool = lares::model::get_pool(db.path(), Default::default())?;
+        let conn = pool.get()?;
+        let feeds = lares::model::Feed::all(&conn)?;
+        assert_eq!(feeds.len(), 1);
+        assert_eq!(feeds[0].error_count, 0);
+        let items = lares::model::Item::all(&conn)?;
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_saved);
+        assert_eq!(lares::model::Group::all(&conn)?.len(), 1);
+        assert!(lares::model::ItemRevision::by_item(&conn, items[0].id)?.is_empty());
+        let found = lares::model::Item::search(&conn, "announcing", &Default::default(), 10)?;
+        assert_eq!(found.len(), 1);
+
+        // crawling the feed again neither duplicates stored items nor resets their flags
+        let lares = Lares {
+            db,
+            pool: pool.clone(),
+        };
+        let (addr, _server) = lares.run_fixture_server()?;
+        conn.execute(
+            "UPDATE `feed` SET `url` = ?1 WHERE `id` = ?2",
+            rusqlite::params![format!("{}/rust.xml", addr), feeds[0].id],
+        )?;
+        lares
+            .cmd()?
+            .args(&["feed", "crawl", &feeds[0].id.to_string()])
+            .unwrap();
+        let crawled = lares::model::Item::all(&conn)?;
+        assert_eq!(crawled.len(), 10);
+        let kept = crawled
+            .iter()
+            .filter(|item| item.url == items[0].url)
+            .collect::<Vec<_>>();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].id, items[0].id);
+        assert_eq!(
+            (kept[0].is_read, kept[0].is_saved),
+            (items[0].is_read, items[0].is_saved)
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_crawl_concurrent_api() -> Result<()> {
+    let lares = Lares::new()?;
+    let (addr, _server) = lares.run_fixture_server()?;
+
+    let feeds = {
+        let conn = lares.pool.get()?;
+        (0..8)
+            .map(|i| {
+                lares::model::Feed::new(
+                    format!("Feed {}", i),
+                    format!("{}/rust.xml?{}", addr, i),
+                    addr.clone(),
+                )
+                .insert(&conn)
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    // each feed is crawled by its own process, while the API reads and writes in this one
+    let crawlers = feeds
+        .iter()
+        .map(|feed| {
+            let mut cmd = lares.cmd()?;
+            cmd.args(&["feed", "crawl", &feed.id.to_string()]);
+            Ok(std::thread::spawn(move || cmd.ok().map(|_| ())))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for _ in 0..50 {
+        lares.fever("items", "")?;
+        lares.fever("unread_item_ids", "")?;
+        lares.fever("", "mark=group&as=read&id=0&before=1600000000")?;
+    }
+
+    for crawler in crawlers {
+        crawler
+            .join()
+            .map_err(|_| anyhow!("crawler panicked"))?
+            .map_err(|e| anyhow!(e.to_string()))?;
+    }
+
+    let response = lares.fever("items", "")?;
+    assert_eq!(response["total_items"], 80);
+
+    Ok(())
+}